@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::convert::npf;
+use crate::frontmatter::compute;
+use crate::links::normalize;
+use crate::utils;
+
+pub const FILE_NAME: &str = ".mdparser.toml";
+
+#[derive(Debug)]
+pub enum ConfigErr {
+    Io { path: PathBuf, err: std::io::Error },
+    Parse { path: PathBuf, err: toml::de::Error },
+}
+
+/// Project configuration, read from a `.mdparser.toml` file. Every field is
+/// optional, and the defaults match [`utils::default_options`].
+///
+/// ```toml
+/// [extensions]
+/// tables = true
+/// footnotes = true
+///
+/// [render]
+/// width = 80
+/// smart = true
+///
+/// [normalize]
+/// strip_params = ["utm_*", "ref"]
+///
+/// [compute.keys]
+/// word_count = "words"
+///
+/// [npf]
+/// base_url = "https://blog.example/"
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub extensions: Extensions,
+    pub render: Render,
+    /// Rules of `links normalize`, and of `--normalize-links` when converting
+    pub normalize: normalize::Options,
+    /// Keys and settings of `frontmatter compute`
+    pub compute: compute::Options,
+    pub npf: npf::Options,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Extensions {
+    pub strikethrough: bool,
+    pub tables: bool,
+    pub footnotes: bool,
+    pub tasklists: bool,
+    pub autolink: bool,
+    pub superscript: bool,
+    pub description_lists: bool,
+    /// `[[Page]]` and `[[Page|alias]]` links
+    pub wikilinks: bool,
+}
+impl Default for Extensions {
+    fn default() -> Self {
+        Self {
+            strikethrough: true,
+            tables: false,
+            footnotes: false,
+            tasklists: false,
+            autolink: false,
+            superscript: false,
+            description_lists: false,
+            wikilinks: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Render {
+    /// Column to wrap paragraphs at when writing Markdown, 0 to not wrap
+    pub width: usize,
+    /// Treat newlines in paragraphs as hard line breaks
+    pub hardbreaks: bool,
+    /// Convert quotes, dashes and ellipses to their typographic characters
+    pub smart: bool,
+}
+impl Default for Render {
+    fn default() -> Self {
+        Self {
+            width: 100,
+            hardbreaks: false,
+            smart: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ConfigErr> {
+        let file = std::fs::read_to_string(path).map_err(|err| ConfigErr::Io {
+            path: path.to_path_buf(),
+            err,
+        })?;
+        let mut config: Self = toml::from_str(&file).map_err(|err| ConfigErr::Parse {
+            path: path.to_path_buf(),
+            err,
+        })?;
+        // Paths in the file are relative to its directory
+        if let (Some(dir), Some(wikilinks)) = (path.parent(), &mut config.npf.wikilinks.directory) {
+            *wikilinks = dir.join(&*wikilinks);
+        }
+        Ok(config)
+    }
+
+    /// Looks for a `.mdparser.toml` in `dir` and each of its parents, loading
+    /// the first one found. Without one, the default configuration is used.
+    pub fn discover(dir: &Path) -> Result<Self, ConfigErr> {
+        match find(dir) {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn options(&self) -> comrak::Options {
+        let mut opts = utils::default_options();
+
+        opts.extension.strikethrough = self.extensions.strikethrough;
+        opts.extension.table = self.extensions.tables;
+        opts.extension.footnotes = self.extensions.footnotes;
+        opts.extension.tasklist = self.extensions.tasklists;
+        opts.extension.autolink = self.extensions.autolink;
+        opts.extension.superscript = self.extensions.superscript;
+        opts.extension.description_lists = self.extensions.description_lists;
+        opts.extension.wikilinks_title_after_pipe = self.extensions.wikilinks;
+
+        opts.render.width = self.render.width;
+        opts.render.hardbreaks = self.render.hardbreaks;
+        opts.parse.smart = self.render.smart;
+
+        opts
+    }
+}
+
+fn find(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.canonicalize().ok()?;
+    dir.ancestors()
+        .map(|d| d.join(FILE_NAME))
+        .find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Config, FILE_NAME};
+
+    #[test]
+    fn discover_config() {
+        let dir = std::env::temp_dir().join(format!("mdparser-config-{}", std::process::id()));
+        fs::create_dir_all(dir.join("posts")).unwrap();
+        fs::write(
+            dir.join(FILE_NAME),
+            "[extensions]\ntables = true\n\n[render]\nwidth = 0\n\n\
+            [npf]\nbase_url = \"https://blog.example/\"\n\n\
+            [npf.wikilinks]\ndirectory = \"notes\"\n",
+        )
+        .unwrap();
+
+        let config = Config::discover(&dir.join("posts")).unwrap();
+        let options = config.options();
+        assert!(options.extension.table);
+        assert!(options.extension.strikethrough);
+        assert!(!options.extension.footnotes);
+        assert_eq!(options.render.width, 0);
+        assert_eq!(
+            config.npf.base_url.map(|u| u.to_string()),
+            Some(String::from("https://blog.example/"))
+        );
+        assert_eq!(
+            config.npf.wikilinks.directory,
+            Some(dir.canonicalize().unwrap().join("notes"))
+        );
+
+        fs::write(dir.join(FILE_NAME), "[render]\nwrap = 80\n").unwrap();
+        assert!(Config::discover(&dir).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
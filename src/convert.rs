@@ -3,4 +3,5 @@ pub mod npf;
 #[derive(Clone, Debug, clap::ValueEnum)]
 pub enum Formats {
     NPF,
+    HTML,
 }
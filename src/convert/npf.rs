@@ -2,11 +2,16 @@ use std::{borrow::Borrow, cell::RefCell, path, str::FromStr};
 
 use comrak::{
     arena_tree::{Children, Node},
-    nodes::{Ast, NodeValue},
+    nodes::{Ast, NodeLink, NodeValue},
 };
+use serde::Deserialize;
 
+use crate::links::normalize;
+
+pub mod ansi;
 pub mod attributions;
 pub mod content_blocks;
+pub mod html;
 pub mod layout_blocks;
 pub mod objects;
 pub mod text_formatting;
@@ -19,10 +24,11 @@ use text_formatting::{FormatTypeBold, FormatTypeItalic, FormatValue};
 
 use text_formatting::{FormatTypeLink, FormatTypeMention, FormatTypeStrikeThrough};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum NPFConvertError {
     TODO,
     InvalidURL { url: String, err: url::ParseError },
+    UnresolvedWikilink(String),
 }
 
 #[cfg(any(feature = "npf-runtime-asserts", test, debug_assertions))]
@@ -78,7 +84,7 @@ impl<'a> TryFrom<&'a Node<'a, RefCell<Ast>>> for objects::Post {
             }
             NodeValue::BlockQuote => {
                 let mut p = Self::try_from(node.children())?.join_content("\n\n");
-                if p.content.len() > 0 {
+                if !p.content.is_empty() {
                     if let BlockValue::Text(ref mut t) = &mut p.content[0] {
                         t.subtype = Some(BlockTextSubtype::Indented);
                     }
@@ -87,7 +93,7 @@ impl<'a> TryFrom<&'a Node<'a, RefCell<Ast>>> for objects::Post {
             }
             NodeValue::Text(t) => {
                 let mut post = Self::new(0);
-                let block_text = BlockText::from(String::from(t.clone()));
+                let block_text = BlockText::from(t.clone());
                 post.content.push(BlockValue::Text(block_text));
 
                 #[cfg(any(feature = "npf-runtime-asserts", test, debug_assertions))]
@@ -193,6 +199,9 @@ impl<'a> TryFrom<&'a Node<'a, RefCell<Ast>>> for objects::Post {
                     }),
                 }
             }
+            // Wikilinks are only resolved to URLs by from_with, so here
+            // just their text is kept
+            NodeValue::WikiLink(_) => Ok(Self::try_from(node.children())?.fold_content()),
             NodeValue::SoftBreak => {
                 let mut post = Self::new(0);
                 post.content.push(BlockValue::Text(BlockText::from(" ")));
@@ -208,13 +217,7 @@ impl<'a> TryFrom<&'a Node<'a, RefCell<Ast>>> for objects::Post {
                     .fold_content()
                     .content
                     .iter()
-                    .find(|b| {
-                        if let BlockValue::Text(_) = b {
-                            true
-                        } else {
-                            false
-                        }
-                    })
+                    .find(|b| matches!(b, BlockValue::Text(_)))
                     .unwrap_or(BlockValue::Text(BlockText::new("")).borrow())
                     .to_owned();
                 let alt_text = if let BlockValue::Text(t) = alt_text {
@@ -251,6 +254,140 @@ pub fn from<'a>(node: &'a Node<'a, RefCell<Ast>>) -> Result<objects::Post, NPFCo
     objects::Post::try_from(node)
 }
 
+/// Preferences for the conversion, usually set in the project's configuration.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Options {
+    /// Relative links and images are resolved against this URL
+    pub base_url: Option<url::Url>,
+    /// What to do with links that aren't valid URLs, or wikilinks that
+    /// couldn't be resolved
+    pub invalid_links: InvalidLinks,
+    pub wikilinks: WikilinkOptions,
+    /// Canonicalize the URLs of links and images, removing tracking parameters
+    pub normalize: Option<normalize::Options>,
+}
+
+/// How `[[Page]]` links are resolved to URLs. The frontmatter of the target
+/// file is tried first, then the pattern.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WikilinkOptions {
+    /// URL with `{slug}` and `{name}` placeholders, for example
+    /// `https://blog.example/tagged/{slug}`
+    pub pattern: Option<String>,
+    /// Directory with the target files, `Page.md` for `[[Page]]`. Relative
+    /// to the configuration file, or to the document without one
+    pub directory: Option<path::PathBuf>,
+    /// Frontmatter key of the target file with its URL
+    pub url_key: Option<String>,
+}
+impl WikilinkOptions {
+    pub fn resolve(&self, target: &str) -> Option<String> {
+        let name = target.split('#').next().unwrap_or(target).trim();
+        let from_file = self.directory.as_ref().and_then(|dir| {
+            // Names like `../secret` or `/etc/passwd` can't reach outside of
+            // the directory
+            let dir = dir.canonicalize().ok()?;
+            let path = dir.join(format!("{}.md", name)).canonicalize().ok()?;
+            if !path.starts_with(&dir) {
+                return None;
+            }
+            let file = std::fs::read_to_string(path).ok()?;
+            let frontmatter = crate::document::Document::parse(&file).frontmatter().ok()?;
+            let key = self.url_key.as_deref().unwrap_or("post_url");
+            match frontmatter.get(String::from(key))? {
+                serde_yaml::Value::String(url) => Some(url.clone()),
+                _ => None,
+            }
+        });
+        from_file.or_else(|| {
+            let pattern = self.pattern.as_ref()?;
+            let name = url::form_urlencoded::byte_serialize(name.as_bytes())
+                .collect::<String>()
+                .replace('+', "%20");
+            Some(
+                pattern
+                    .replace("{slug}", &slug(target))
+                    .replace("{name}", &name),
+            )
+        })
+    }
+}
+
+/// Lowercase words of the name separated by dashes, `Page Name` is `page-name`.
+fn slug(name: &str) -> String {
+    let name = name.split('#').next().unwrap_or(name);
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InvalidLinks {
+    /// Fail the conversion
+    #[default]
+    Error,
+    /// Keep only the link's text
+    Text,
+}
+
+/// Converts the document like [`from`], applying the options to its links and
+/// images and resolving its wikilinks first. The AST is changed in place.
+pub fn from_with<'a>(
+    node: &'a Node<'a, RefCell<Ast>>,
+    options: &Options,
+) -> Result<objects::Post, NPFConvertError> {
+    for n in node.descendants().collect::<Vec<_>>() {
+        let wikilink = match &n.data.borrow().value {
+            NodeValue::WikiLink(l) => Some(l.url.clone()),
+            _ => None,
+        };
+        if let Some(target) = wikilink {
+            match (options.wikilinks.resolve(&target), &options.invalid_links) {
+                (Some(url), _) => {
+                    n.data.borrow_mut().value = NodeValue::Link(NodeLink {
+                        url,
+                        title: String::new(),
+                    })
+                }
+                (None, InvalidLinks::Error) => {
+                    return Err(NPFConvertError::UnresolvedWikilink(target))
+                }
+                (None, InvalidLinks::Text) => {}
+            }
+        }
+
+        let invalid = match &mut n.data.borrow_mut().value {
+            NodeValue::Link(l) | NodeValue::Image(l) => {
+                if let Some(normalize) = &options.normalize {
+                    l.url = normalize.normalize(&l.url);
+                }
+                if let Some(base) = &options.base_url {
+                    if let Err(url::ParseError::RelativeUrlWithoutBase) = url::Url::parse(&l.url) {
+                        if let Ok(url) = base.join(&l.url) {
+                            l.url = url.to_string();
+                        }
+                    }
+                }
+                url::Url::parse(&l.url).is_err()
+            }
+            _ => false,
+        };
+        let is_link = matches!(n.data.borrow().value, NodeValue::Link(_));
+        if invalid && is_link {
+            if let InvalidLinks::Text = options.invalid_links {
+                n.children().for_each(|c| n.insert_before(c));
+                n.detach();
+            }
+        }
+    }
+    from(node)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -388,9 +525,9 @@ mod tests {
     fn text_block_plain() {
         let markdown = "Hello world, this is a test of markdown.";
         let arena = Arena::new();
-        let ast = comrak::parse_document(&arena, &markdown, &utils::default_options());
+        let ast = comrak::parse_document(&arena, markdown, &utils::default_options());
 
-        let npf = npf::from(&ast).unwrap();
+        let npf = npf::from(ast).unwrap();
 
         assert_eq_text!(&npf.content[0], "Hello world, this is a test of markdown.");
     }
@@ -399,9 +536,9 @@ mod tests {
     fn text_block_formatting() {
         let markdown = "Hello world, **this is a test of markdown**.";
         let arena = Arena::new();
-        let ast = comrak::parse_document(&arena, &markdown, &utils::default_options());
+        let ast = comrak::parse_document(&arena, markdown, &utils::default_options());
 
-        let npf = npf::from(&ast).unwrap();
+        let npf = npf::from(ast).unwrap();
 
         let formatting = vec![FormatValue::Bold(FormatTypeBold::from(13..39))];
         let npf_formatting = extrac_formatting!(&npf.content[0]);
@@ -414,9 +551,9 @@ mod tests {
     fn text_block_formatting_nested() {
         let markdown = "Hello world, **this [is a test of](https://guz.one) markdown**.";
         let arena = Arena::new();
-        let ast = comrak::parse_document(&arena, &markdown, &utils::default_options());
+        let ast = comrak::parse_document(&arena, markdown, &utils::default_options());
 
-        let npf = npf::from(&ast).unwrap();
+        let npf = npf::from(ast).unwrap();
 
         let formatting = vec![
             FormatValue::Link(FormatTypeLink::new(
@@ -436,9 +573,9 @@ mod tests {
         // This isn't "valid" markdown, so the conversion should reflect that
         let markdown = "Hello [world, *this is](https://guz.one) a test of markdown*.";
         let arena = Arena::new();
-        let ast = comrak::parse_document(&arena, &markdown, &utils::default_options());
+        let ast = comrak::parse_document(&arena, markdown, &utils::default_options());
 
-        let npf = npf::from(&ast).unwrap();
+        let npf = npf::from(ast).unwrap();
 
         let formatting = vec![FormatValue::Link(FormatTypeLink::new(
             6..21,
@@ -457,9 +594,9 @@ mod tests {
     fn text_block_formatting_complex() {
         let markdown = "Hello [world, *this is*](https://guz.one) ~~a test of markdown~~.";
         let arena = Arena::new();
-        let ast = comrak::parse_document(&arena, &markdown, &utils::default_options());
+        let ast = comrak::parse_document(&arena, markdown, &utils::default_options());
 
-        let npf = npf::from(&ast).unwrap();
+        let npf = npf::from(ast).unwrap();
 
         let formatting = vec![
             FormatValue::Italic(FormatTypeItalic::from(13..20)),
@@ -494,9 +631,9 @@ mod tests {
                         - [Gustavo \"Guz\" L. de Mello](https://guz.one), Apr 16, 12.2024";
 
         let arena = Arena::new();
-        let ast = comrak::parse_document(&arena, &markdown, &utils::default_options());
+        let ast = comrak::parse_document(&arena, markdown, &utils::default_options());
 
-        let npf = npf::from(&ast).unwrap();
+        let npf = npf::from(ast).unwrap();
         let formatting = vec![
             FormatValue::Bold(FormatTypeBold::from(3..6)),
             FormatValue::StrikeThrough(FormatTypeStrikeThrough::from(67..71)),
@@ -527,4 +664,89 @@ mod tests {
             - Gustavo \"Guz\" L. de Mello, Apr 16, 12.2024"
         );
     }
+
+    #[test]
+    fn options_links() {
+        let markdown =
+            "A [relative](posts/one) link and an ![image](cat.png) and [invalid](http://)";
+        let arena = Arena::new();
+        let ast = comrak::parse_document(&arena, markdown, &utils::default_options());
+
+        let options = npf::Options {
+            base_url: Some(url::Url::parse("https://guz.one/blog/").unwrap()),
+            invalid_links: npf::InvalidLinks::Text,
+            ..Default::default()
+        };
+        let npf = npf::from_with(ast, &options).unwrap();
+
+        let formatting = extrac_formatting!(&npf.content[0]);
+        let expected = vec![FormatValue::Link(FormatTypeLink::new(
+            2..10,
+            url::Url::parse("https://guz.one/blog/posts/one").unwrap(),
+        ))];
+        assert_eq_formatting!(expected, formatting);
+        assert_eq_text!(&npf.content[2], " and invalid");
+        if let BlockValue::Image(i) = &npf.content[1] {
+            assert_eq!(
+                i.media[0].url.as_ref().map(|u| u.to_string()),
+                Some(String::from("https://guz.one/blog/cat.png"))
+            );
+        } else {
+            panic!("Expected an image block {:#?}", npf.content[1]);
+        }
+    }
+
+    #[test]
+    fn options_wikilinks() {
+        let dir = std::env::temp_dir().join(format!("mdparser-wikilinks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("Page.md"),
+            "---\npost_url: https://guz.one/post/1\n---\n\n# Page\n",
+        )
+        .unwrap();
+
+        let markdown = "See [[Page|this]] and [[Other Page]]";
+        let mut parse_options = utils::default_options();
+        parse_options.extension.wikilinks_title_after_pipe = true;
+        let arena = Arena::new();
+        let ast = comrak::parse_document(&arena, markdown, &parse_options);
+
+        let mut options = npf::Options::default();
+        options.wikilinks.directory = Some(dir.clone());
+        let npf = npf::from_with(ast, &options);
+        assert!(
+            matches!(npf, Err(npf::NPFConvertError::UnresolvedWikilink(t)) if t == "Other Page")
+        );
+
+        let outside = dir.with_extension("md");
+        std::fs::write(&outside, "---\npost_url: https://guz.one/secret\n---\n").unwrap();
+        let name = dir.file_name().unwrap().to_str().unwrap();
+        assert_eq!(options.wikilinks.resolve(&format!("../{}", name)), None);
+        assert_eq!(
+            options.wikilinks.resolve(dir.to_str().unwrap()),
+            None,
+            "absolute names outside the directory aren't read"
+        );
+        std::fs::remove_file(outside).unwrap();
+
+        options.wikilinks.pattern = Some(String::from("https://guz.one/tagged/{slug}"));
+        let npf = npf::from_with(ast, &options).unwrap();
+
+        let formatting = extrac_formatting!(&npf.content[0]);
+        let expected = vec![
+            FormatValue::Link(FormatTypeLink::new(
+                4..8,
+                url::Url::parse("https://guz.one/post/1").unwrap(),
+            )),
+            FormatValue::Link(FormatTypeLink::new(
+                13..23,
+                url::Url::parse("https://guz.one/tagged/other-page").unwrap(),
+            )),
+        ];
+        assert_eq_formatting!(expected, formatting);
+        assert_eq_text!(&npf.content[0], "See this and Other Page");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
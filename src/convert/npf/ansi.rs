@@ -0,0 +1,359 @@
+use std::fmt::Write;
+
+use super::content_blocks::{self, BlockText, BlockTextSubtype, BlockValue};
+use super::layout_blocks;
+use super::objects::{self, Media};
+use super::text_formatting::FormatValue;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "1";
+const DIM: &str = "2";
+const ITALIC: &str = "3";
+const UNDERLINE: &str = "4";
+const STRIKETHROUGH: &str = "9";
+
+/// Renders the post for a terminal, styling text with ANSI escape codes and
+/// using OSC 8 hyperlinks. Media blocks are shown as placeholders.
+pub fn render(post: &objects::Post) -> String {
+    let mut out = String::new();
+
+    post.trail.iter().for_each(|t| {
+        let name = t
+            .blog
+            .as_ref()
+            .and_then(|b| b.name.clone())
+            .unwrap_or_else(|| String::from("[deleted]"));
+        let _ = writeln!(out, "{}:\n", style(&name, &[BOLD]));
+        out.push_str(&render_blocks(&t.content, &t.layout));
+        let _ = writeln!(out, "{}\n", style(&"─".repeat(40), &[DIM]));
+    });
+
+    out.push_str(&render_blocks(&post.content, &post.layout));
+
+    if let Some(tags) = post.tags.as_ref().filter(|t| !t.is_empty()) {
+        let tags = tags
+            .iter()
+            .map(|t| format!("#{}", t))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(out, "{}", style(&tags, &[DIM]));
+    }
+
+    out
+}
+
+fn render_blocks(
+    content: &[content_blocks::BlockValue],
+    layout: &[layout_blocks::BlockValue],
+) -> String {
+    let truncate_after = layout_blocks::truncate_after(layout);
+    let ask = layout_blocks::ask(layout);
+    let is_ask = |i: usize| ask.is_some_and(|a| a.blocks.contains(&(i as u64)));
+
+    let mut out = String::new();
+    let mut numbers: Vec<u32> = vec![];
+    let mut in_ask = false;
+    let mut truncated = false;
+
+    for row in layout_blocks::rows(content.len(), layout) {
+        if !truncated && truncate_after.is_some_and(|t| row.iter().all(|i| *i > t)) {
+            let _ = writeln!(out, "{}\n", style("── Keep reading ──", &[DIM]));
+            truncated = true;
+        }
+
+        if is_ask(row[0]) && !in_ask {
+            let name = ask
+                .and_then(|a| a.attribution.as_ref())
+                .and_then(|a| a.blog.name.clone())
+                .unwrap_or_else(|| String::from("Anonymous"));
+            let _ = writeln!(out, "{}\n", style(&format!("{} asked:", name), &[BOLD]));
+        }
+        in_ask = is_ask(row[0]);
+        let prefix = if in_ask { "┃ " } else { "" };
+
+        let block = if let [i] = row[..] {
+            match &content[i] {
+                BlockValue::Text(t) if is_list_item(t) => {
+                    let level = t.ident_level.unwrap_or(0) as usize;
+                    numbers.resize(level + 1, 0);
+                    numbers[level] += 1;
+                    render_list_item(t, numbers[level])
+                }
+                block => {
+                    numbers.clear();
+                    render_block(block)
+                }
+            }
+        } else {
+            numbers.clear();
+            row.iter()
+                .map(|i| render_block(&content[*i]))
+                .collect::<Vec<_>>()
+                .join(&style(" │ ", &[DIM]))
+        };
+
+        let block = block
+            .lines()
+            .map(|l| format!("{}{}", prefix, l))
+            .collect::<Vec<_>>()
+            .join("\n");
+        out.push_str(&block);
+
+        // Consecutive list items are kept together, everything else is
+        // separated by a blank line
+        let next_is_item = row
+            .last()
+            .and_then(|i| content.get(i + 1))
+            .is_some_and(|b| matches!(b, BlockValue::Text(t) if is_list_item(t)));
+        if numbers.is_empty() || !next_is_item {
+            out.push_str("\n\n");
+        } else {
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+fn render_block(block: &content_blocks::BlockValue) -> String {
+    match block {
+        BlockValue::Text(t) => match t.subtype {
+            Some(BlockTextSubtype::Heading1) | Some(BlockTextSubtype::Heading2) => {
+                render_text(t, &[BOLD])
+            }
+            Some(BlockTextSubtype::Quirky) => render_text(t, &[ITALIC]),
+            Some(BlockTextSubtype::Quote) => format!("“{}”", render_text(t, &[ITALIC])),
+            Some(BlockTextSubtype::Indented) => indent(&render_text(t, &[]), "│ ", "│ "),
+            Some(BlockTextSubtype::OrderedListItem) | Some(BlockTextSubtype::UnordoredListItem) => {
+                render_list_item(t, 1)
+            }
+            Some(BlockTextSubtype::Chat) | None => render_text(t, &[]),
+        },
+        BlockValue::Image(i) => {
+            let description = i
+                .alt_text
+                .clone()
+                .filter(|a| !a.is_empty())
+                .or_else(|| i.media.iter().find_map(Media::src))
+                .unwrap_or_default();
+            let placeholder = placeholder("image", &description);
+            match i.media.iter().find_map(|m| m.url.as_ref()) {
+                Some(url) => hyperlink(url.as_str(), &placeholder),
+                None => placeholder,
+            }
+        }
+        BlockValue::Link(l) => {
+            let title = l.title.clone().unwrap_or_else(|| l.url.to_string());
+            hyperlink(l.url.as_str(), &placeholder("link", &title))
+        }
+        BlockValue::Audio(a) => {
+            let description = [a.title.as_deref(), a.artist.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" - ");
+            let url = a
+                .media
+                .as_ref()
+                .and_then(|m| m.url.as_ref())
+                .or(a.url.as_ref());
+            match url {
+                Some(url) => hyperlink(url.as_str(), &placeholder("audio", &description)),
+                None => placeholder("audio", &description),
+            }
+        }
+        BlockValue::Video(v) => {
+            let url = v
+                .media
+                .as_ref()
+                .and_then(|m| m.url.as_ref())
+                .or(v.url.as_ref());
+            let description = v
+                .provider
+                .clone()
+                .or_else(|| url.map(|u| u.to_string()))
+                .unwrap_or_default();
+            match url {
+                Some(url) => hyperlink(url.as_str(), &placeholder("video", &description)),
+                None => placeholder("video", &description),
+            }
+        }
+    }
+}
+
+fn render_list_item(text: &BlockText, number: u32) -> String {
+    let indent_level = "  ".repeat(text.ident_level.unwrap_or(0) as usize);
+    let bullet = match text.subtype {
+        Some(BlockTextSubtype::OrderedListItem) => format!("{}. ", number),
+        _ => String::from("• "),
+    };
+    indent(
+        &render_text(text, &[]),
+        &format!("{}{}", indent_level, bullet),
+        &format!("{}{}", indent_level, " ".repeat(bullet.chars().count())),
+    )
+}
+
+fn render_text(text: &BlockText, base: &[&str]) -> String {
+    let chars = text.text.chars().collect::<Vec<_>>();
+    let mut out = String::new();
+
+    for span in text.spans() {
+        let mut codes = base.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let mut link = None;
+        let mut content = span.text.clone();
+
+        for format in &span.formatting {
+            match format {
+                FormatValue::Bold(_) => codes.push(BOLD.to_string()),
+                FormatValue::Italic(_) => codes.push(ITALIC.to_string()),
+                FormatValue::StrikeThrough(_) => codes.push(STRIKETHROUGH.to_string()),
+                FormatValue::Small(_) => codes.push(DIM.to_string()),
+                FormatValue::Color(c) => codes.extend(color(&c.hex)),
+                FormatValue::Link(l) => {
+                    codes.push(UNDERLINE.to_string());
+                    link = Some(l.url.to_string());
+                }
+                FormatValue::Mention(m) => {
+                    // The mention is written once, as @name, in place of
+                    // its first span
+                    let range = m.range();
+                    content = if span.range.start == range.start {
+                        let start = (range.start as usize).min(chars.len());
+                        let end = (range.end as usize).clamp(start, chars.len());
+                        let name = match &m.blog.name {
+                            Some(name) => name.clone(),
+                            None => chars[start..end].iter().collect(),
+                        };
+                        format!("@{}", name.trim_start_matches('@'))
+                    } else {
+                        String::new()
+                    };
+                    codes.push(BOLD.to_string());
+                    link = m.blog.blog_url().map(|u| u.to_string());
+                }
+            }
+        }
+
+        let styled = if codes.is_empty() {
+            content
+        } else {
+            content
+                .split('\n')
+                .map(|l| format!("\x1b[{}m{}{}", codes.join(";"), l, RESET))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        match link {
+            Some(url) if !styled.is_empty() => out.push_str(&hyperlink(&url, &styled)),
+            _ => out.push_str(&styled),
+        }
+    }
+
+    out
+}
+
+fn is_list_item(text: &BlockText) -> bool {
+    matches!(
+        text.subtype,
+        Some(BlockTextSubtype::OrderedListItem) | Some(BlockTextSubtype::UnordoredListItem)
+    )
+}
+
+fn indent(text: &str, first: &str, rest: &str) -> String {
+    text.split('\n')
+        .enumerate()
+        .map(|(i, l)| format!("{}{}", if i == 0 { first } else { rest }, l))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn placeholder(kind: &str, description: &str) -> String {
+    if description.is_empty() {
+        style(&format!("[{}]", kind), &[DIM])
+    } else {
+        style(&format!("[{}: {}]", kind, description), &[DIM])
+    }
+}
+
+fn style(text: &str, codes: &[&str]) -> String {
+    format!("\x1b[{}m{}{}", codes.join(";"), text, RESET)
+}
+
+fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+fn color(hex: &str) -> Option<String> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(format!(
+        "38;2;{};{};{}",
+        channel(0)?,
+        channel(2)?,
+        channel(4)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use comrak::Arena;
+
+    use crate::convert::npf;
+    use crate::convert::npf::objects::Post;
+    use crate::utils;
+
+    #[test]
+    fn text_styles() {
+        let markdown = "Hello **world**, [link](https://guz.one) ~~gone~~.";
+        let arena = Arena::new();
+        let ast = comrak::parse_document(&arena, markdown, &utils::default_options());
+
+        let out = npf::ansi::render(&npf::from(ast).unwrap());
+
+        assert_eq!(
+            out,
+            "Hello \x1b[1mworld\x1b[0m, \
+            \x1b]8;;https://guz.one/\x1b\\\x1b[4mlink\x1b[0m\x1b]8;;\x1b\\ \
+            \x1b[9mgone\x1b[0m.\n\n"
+        );
+    }
+
+    #[test]
+    fn mentions_lists_and_media() {
+        let json = r#"{
+            "object_type": "post",
+            "id": 1,
+            "id_string": "1",
+            "content": [
+                {"type": "text", "text": "hi staff",
+                    "formatting": [{"type": "mention", "start": 3, "end": 8,
+                        "blog": {"uuid": "t:0", "name": "staff"}}]},
+                {"type": "text", "text": "hey you",
+                    "formatting": [
+                        {"type": "mention", "start": 4, "end": 99, "blog": {"uuid": "t:1"}},
+                        {"type": "mention", "start": 6, "end": 2, "blog": {"uuid": "t:2"}}
+                    ]},
+                {"type": "text", "subtype": "ordered-list-item", "text": "one"},
+                {"type": "text", "subtype": "ordered-list-item", "text": "nested", "indent_level": 1},
+                {"type": "text", "subtype": "ordered-list-item", "text": "two"},
+                {"type": "image", "media": [{"identifier": "cat.png"}], "alt_text": "A cat"}
+            ],
+            "layout": [],
+            "trail": []
+        }"#;
+        let post = serde_json::from_str::<Post>(json).unwrap();
+
+        let out = npf::ansi::render(&post);
+
+        assert!(out.starts_with(
+            "hi \x1b]8;;https://staff.tumblr.com/\x1b\\\x1b[1m@staff\x1b[0m\x1b]8;;\x1b\\\n\n"
+        ));
+        assert!(out.contains("hey \x1b[1m@you\x1b[0m"));
+        assert!(out.contains("1. one\n  1. nested\n2. two\n\n"));
+        assert!(out.ends_with("\x1b[2m[image: A cat]\x1b[0m\n\n"));
+    }
+}
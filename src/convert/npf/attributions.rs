@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use super::objects;
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(untagged)]
 pub enum AttributionValue {
@@ -117,7 +118,7 @@ impl AttributionApp {
     }
     fn default() -> Self {
         Self {
-            r#type: String::from("blog"),
+            r#type: String::from("app"),
             url: url::Url::from_str("https://tumblr.com").unwrap(),
             app_name: None,
             display_text: None,
@@ -1,10 +1,11 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{cmp::Reverse, collections::HashMap, ops::Range, str::FromStr};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Serialize};
 
 use super::{attributions, objects, text_formatting::FormatValue};
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize, Clone)]
 #[serde(untagged)]
 pub enum BlockValue {
     Text(BlockText),
@@ -13,9 +14,34 @@ pub enum BlockValue {
     Audio(BlockAudio),
     Video(BlockVideo),
 }
+// Most block fields are optional, so an untagged deserialization would read
+// video blocks as links and so on. Dispatch on "type" instead.
+impl<'de> Deserialize<'de> for BlockValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let result = match value.get("type").and_then(|t| t.as_str()) {
+            Some("text") => serde_json::from_value(value).map(BlockValue::Text),
+            Some("image") => serde_json::from_value(value).map(BlockValue::Image),
+            Some("link") => serde_json::from_value(value).map(BlockValue::Link),
+            Some("audio") => serde_json::from_value(value).map(BlockValue::Audio),
+            Some("video") => serde_json::from_value(value).map(BlockValue::Video),
+            Some(t) => {
+                return Err(de::Error::unknown_variant(
+                    t,
+                    &["text", "image", "link", "audio", "video"],
+                ))
+            }
+            None => return Err(de::Error::missing_field("type")),
+        };
+        result.map_err(de::Error::custom)
+    }
+}
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
-#[serde(rename_all = "snake_case")]
+#[serde(rename_all = "kebab-case")]
 pub enum BlockTextSubtype {
     Heading1,
     Heading2,
@@ -24,6 +50,7 @@ pub enum BlockTextSubtype {
     Indented,
     Chat,
     OrderedListItem,
+    #[serde(rename = "unordered-list-item")]
     UnordoredListItem,
 }
 
@@ -34,6 +61,7 @@ pub struct BlockText {
     pub subtype: Option<BlockTextSubtype>,
     pub text: String,
     pub formatting: Option<Vec<FormatValue>>,
+    #[serde(rename = "indent_level")]
     pub ident_level: Option<u8>,
 }
 impl BlockText {
@@ -47,6 +75,44 @@ impl BlockText {
             self.formatting = Some(vec![format]);
         }
     }
+    /// Splits the text at every formatting boundary, returning each span with
+    /// the formatting that covers it, outermost first.
+    pub fn spans(&self) -> Vec<TextSpan<'_>> {
+        let chars = self.text.chars().collect::<Vec<_>>();
+        let len = chars.len() as u64;
+        let formatting = self.formatting.as_deref().unwrap_or_default();
+
+        let mut bounds = vec![0, len];
+        formatting.iter().for_each(|f| {
+            let range = f.range();
+            bounds.push(range.start.min(len));
+            bounds.push(range.end.min(len));
+        });
+        bounds.sort();
+        bounds.dedup();
+
+        bounds
+            .windows(2)
+            .map(|w| {
+                let mut formatting = formatting
+                    .iter()
+                    .filter(|f| {
+                        let range = f.range();
+                        range.start <= w[0] && w[1] <= range.end
+                    })
+                    .collect::<Vec<_>>();
+                formatting.sort_by_key(|f| {
+                    let range = f.range();
+                    (range.start, Reverse(range.end))
+                });
+                TextSpan {
+                    text: chars[w[0] as usize..w[1] as usize].iter().collect(),
+                    range: w[0]..w[1],
+                    formatting,
+                }
+            })
+            .collect()
+    }
     fn default() -> Self {
         Self {
             r#type: String::from("text"),
@@ -57,6 +123,13 @@ impl BlockText {
         }
     }
 }
+#[derive(Debug, Clone)]
+pub struct TextSpan<'a> {
+    pub text: String,
+    pub range: Range<u64>,
+    pub formatting: Vec<&'a FormatValue>,
+}
+
 impl From<String> for BlockText {
     fn from(value: String) -> Self {
         Self {
@@ -176,11 +249,7 @@ impl BlockAudio {
         Self::from(url)
     }
     pub fn is_valid(&self) -> bool {
-        if self.url.is_some() || self.media.is_some() {
-            true
-        } else {
-            false
-        }
+        self.url.is_some() || self.media.is_some()
     }
     fn default() -> Self {
         Self {
@@ -236,15 +305,11 @@ impl BlockVideo {
         Self::from(url)
     }
     pub fn is_valid(&self) -> bool {
-        if self.url.is_some() || self.media.is_some() {
-            true
-        } else {
-            false
-        }
+        self.url.is_some() || self.media.is_some()
     }
     fn default() -> Self {
         Self {
-            r#type: String::from("audio"),
+            r#type: String::from("video"),
             media: None,
             url: None,
             provider: None,
@@ -0,0 +1,492 @@
+use std::fmt::Write;
+
+use super::content_blocks::{self, BlockText, BlockTextSubtype, BlockValue};
+use super::layout_blocks;
+use super::objects::{self, Media};
+use super::text_formatting::FormatValue;
+
+const STYLE: &str = "\
+body { background: #001935; margin: 0; padding: 2rem 0; }
+.npf-post { background: #fff; color: #000; width: 540px; margin: 0 auto; padding: 1rem 0; \
+border-radius: 8px; font: 15px/1.5 \"Favorit\", \"Helvetica Neue\", Helvetica, Arial, sans-serif; }
+.npf-post > * > *, .npf-trail-item > header { padding: 0 20px; }
+.npf-trail-item { border-bottom: 1px solid #e7e7e7; margin-bottom: 1rem; }
+.npf-trail-item > header, .npf-ask > header { font-weight: bold; }
+.npf-row { display: flex; gap: 4px; padding: 0; }
+.npf-row > * { flex: 1; min-width: 0; }
+.npf-image { margin: 0; }
+.npf-image img, .npf-video video, .npf-link img { display: block; width: 100%; }
+.npf-image figcaption, .npf-audio figcaption { color: #666; font-size: 13px; }
+.npf-quirky { font-family: \"Fairwater Script\", cursive; font-size: 24px; }
+.npf-quote { font-family: Georgia, serif; font-size: 20px; }
+.npf-chat { font-family: \"Courier New\", monospace; }
+.npf-indented { border-left: 3px solid #e7e7e7; margin: 0 20px; padding-left: 17px; }
+.npf-link { display: block; border: 1px solid #e7e7e7; border-radius: 4px; color: inherit; \
+text-decoration: none; margin: 0 20px; padding: 0; }
+.npf-link > * { margin: 8px 12px; }
+.npf-ask { background: #f1f1f1; border-radius: 4px; margin: 0 20px 1rem; padding: 8px 0; }
+.npf-read-more summary { color: #00b8ff; cursor: pointer; font-weight: bold; }
+.npf-tags { color: #666; }
+.npf-tags span { margin-right: 8px; }
+";
+
+/// Renders the post as an HTML fragment, approximating how Tumblr's
+/// dashboard would display it.
+pub fn render(post: &objects::Post) -> String {
+    let mut html = String::from("<article class=\"npf-post\">\n");
+
+    post.trail.iter().for_each(|t| {
+        let name = t
+            .blog
+            .as_ref()
+            .and_then(|b| b.name.clone())
+            .unwrap_or_else(|| String::from("[deleted]"));
+        let _ = writeln!(
+            html,
+            "<section class=\"npf-trail-item\">\n<header>{}</header>\n{}</section>",
+            escape(&name),
+            render_blocks(&t.content, &t.layout)
+        );
+    });
+
+    let _ = writeln!(
+        html,
+        "<section class=\"npf-content\">\n{}</section>",
+        render_blocks(&post.content, &post.layout)
+    );
+
+    if let Some(tags) = post.tags.as_ref().filter(|t| !t.is_empty()) {
+        let tags = tags
+            .iter()
+            .map(|t| format!("<span>#{}</span>", escape(t)))
+            .collect::<String>();
+        let _ = writeln!(html, "<footer class=\"npf-tags\">{}</footer>", tags);
+    }
+
+    html.push_str("</article>\n");
+    html
+}
+
+/// Renders the post as a standalone HTML page, for previewing in a browser.
+pub fn render_document(post: &objects::Post) -> String {
+    format!(
+        "<!DOCTYPE html>\n\
+        <html>\n\
+        <head>\n\
+        <meta charset=\"utf-8\">\n\
+        <style>\n{}</style>\n\
+        </head>\n\
+        <body>\n{}</body>\n\
+        </html>\n",
+        STYLE,
+        render(post)
+    )
+}
+
+fn render_blocks(
+    content: &[content_blocks::BlockValue],
+    layout: &[layout_blocks::BlockValue],
+) -> String {
+    let truncate_after = layout_blocks::truncate_after(layout);
+    let ask = layout_blocks::ask(layout);
+    let is_ask = |i: usize| ask.is_some_and(|a| a.blocks.contains(&(i as u64)));
+
+    let mut html = String::new();
+    let mut lists = Lists::default();
+    let mut in_ask = false;
+    let mut truncated = false;
+
+    for row in layout_blocks::rows(content.len(), layout) {
+        if !truncated && truncate_after.is_some_and(|t| row.iter().all(|i| *i > t)) {
+            html.push_str(&lists.close_all());
+            if in_ask {
+                html.push_str("</div>\n");
+                in_ask = false;
+            }
+            html.push_str("<details class=\"npf-read-more\">\n<summary>Keep reading</summary>\n");
+            truncated = true;
+        }
+
+        if is_ask(row[0]) != in_ask {
+            html.push_str(&lists.close_all());
+            if in_ask {
+                html.push_str("</div>\n");
+            } else {
+                let name = ask
+                    .and_then(|a| a.attribution.as_ref())
+                    .and_then(|a| a.blog.name.clone())
+                    .unwrap_or_else(|| String::from("Anonymous"));
+                let _ = writeln!(
+                    html,
+                    "<div class=\"npf-ask\">\n<header>{} asked:</header>",
+                    escape(&name)
+                );
+            }
+            in_ask = !in_ask;
+        }
+
+        if let [i] = row[..] {
+            match &content[i] {
+                BlockValue::Text(t) if list_kind(t).is_some() => {
+                    html.push_str(&lists.item(t));
+                }
+                block => {
+                    html.push_str(&lists.close_all());
+                    html.push_str(&render_block(block));
+                }
+            }
+        } else {
+            html.push_str(&lists.close_all());
+            html.push_str("<div class=\"npf-row\">\n");
+            row.iter()
+                .for_each(|i| html.push_str(&render_block(&content[*i])));
+            html.push_str("</div>\n");
+        }
+    }
+
+    html.push_str(&lists.close_all());
+    if in_ask {
+        html.push_str("</div>\n");
+    }
+    if truncated {
+        html.push_str("</details>\n");
+    }
+    html
+}
+
+fn render_block(block: &content_blocks::BlockValue) -> String {
+    match block {
+        BlockValue::Text(t) => {
+            let text = render_text(t);
+            match t.subtype {
+                Some(BlockTextSubtype::Heading1) => format!("<h1>{}</h1>\n", text),
+                Some(BlockTextSubtype::Heading2) => format!("<h2>{}</h2>\n", text),
+                Some(BlockTextSubtype::Quirky) => format!("<p class=\"npf-quirky\">{}</p>\n", text),
+                Some(BlockTextSubtype::Quote) => format!("<p class=\"npf-quote\">{}</p>\n", text),
+                Some(BlockTextSubtype::Chat) => format!("<p class=\"npf-chat\">{}</p>\n", text),
+                Some(BlockTextSubtype::Indented) => {
+                    format!("<blockquote class=\"npf-indented\">{}</blockquote>\n", text)
+                }
+                Some(BlockTextSubtype::OrderedListItem)
+                | Some(BlockTextSubtype::UnordoredListItem) => {
+                    let mut lists = Lists::default();
+                    format!("{}{}", lists.item(t), lists.close_all())
+                }
+                None => format!("<p>{}</p>\n", text),
+            }
+        }
+        BlockValue::Image(i) => {
+            let src = i.media.iter().find_map(Media::src).unwrap_or_default();
+            let alt = i.alt_text.as_deref().unwrap_or_default();
+            let caption = i
+                .caption
+                .as_ref()
+                .map(|c| format!("<figcaption>{}</figcaption>", escape(c)))
+                .unwrap_or_default();
+            format!(
+                "<figure class=\"npf-image\"><img src=\"{}\" alt=\"{}\">{}</figure>\n",
+                escape(&src),
+                escape(alt),
+                caption
+            )
+        }
+        BlockValue::Link(l) => {
+            let poster = l
+                .poster
+                .as_ref()
+                .and_then(Media::src)
+                .map(|src| format!("<img src=\"{}\" alt=\"\">", escape(&src)))
+                .unwrap_or_default();
+            let title = l.title.clone().unwrap_or_else(|| l.url.to_string());
+            let description = l
+                .description
+                .as_ref()
+                .map(|d| format!("<p>{}</p>", escape(d)))
+                .unwrap_or_default();
+            let site = l
+                .site_name
+                .clone()
+                .or_else(|| l.url.host_str().map(String::from))
+                .unwrap_or_default();
+            format!(
+                "<a class=\"npf-link\" href=\"{}\">{}<strong>{}</strong>{}<span>{}</span></a>\n",
+                escape(l.url.as_str()),
+                poster,
+                escape(&title),
+                description,
+                escape(&site)
+            )
+        }
+        BlockValue::Audio(a) => {
+            let player = match (a.media.as_ref().and_then(|m| m.url.as_ref()), &a.url) {
+                (Some(src), _) => {
+                    format!("<audio controls src=\"{}\"></audio>", escape(src.as_str()))
+                }
+                (None, Some(url)) => format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape(url.as_str()),
+                    escape(url.as_str())
+                ),
+                (None, None) => String::new(),
+            };
+            let caption = [a.title.as_deref(), a.artist.as_deref(), a.album.as_deref()]
+                .into_iter()
+                .flatten()
+                .map(escape)
+                .collect::<Vec<_>>()
+                .join(" - ");
+            format!(
+                "<figure class=\"npf-audio\">{}<figcaption>{}</figcaption></figure>\n",
+                player, caption
+            )
+        }
+        BlockValue::Video(v) => {
+            let poster = v
+                .poster
+                .as_ref()
+                .and_then(Media::src)
+                .map(|src| format!(" poster=\"{}\"", escape(&src)))
+                .unwrap_or_default();
+            let player = match (v.media.as_ref().and_then(|m| m.url.as_ref()), v) {
+                (Some(src), _) => format!(
+                    "<video controls src=\"{}\"{}></video>",
+                    escape(src.as_str()),
+                    poster
+                ),
+                (None, v) if v.embed_iframe.is_some() => {
+                    let iframe = v.embed_iframe.as_ref().unwrap();
+                    format!(
+                        "<iframe src=\"{}\" width=\"{}\" height=\"{}\"></iframe>",
+                        escape(iframe.url.as_str()),
+                        iframe.width,
+                        iframe.height
+                    )
+                }
+                (None, v) => v
+                    .url
+                    .as_ref()
+                    .map(|u| {
+                        format!(
+                            "<a href=\"{}\">{}</a>",
+                            escape(u.as_str()),
+                            escape(u.as_str())
+                        )
+                    })
+                    .unwrap_or_default(),
+            };
+            format!("<figure class=\"npf-video\">{}</figure>\n", player)
+        }
+    }
+}
+
+fn render_text(text: &BlockText) -> String {
+    let mut html = String::new();
+    let mut open: Vec<&FormatValue> = vec![];
+
+    for span in text.spans() {
+        let common = open
+            .iter()
+            .zip(span.formatting.iter())
+            .take_while(|(a, b)| std::ptr::eq(**a, **b))
+            .count();
+        open.drain(common..)
+            .rev()
+            .for_each(|f| html.push_str(close_tag(f)));
+        span.formatting[common..].iter().for_each(|f| {
+            html.push_str(&open_tag(f));
+            open.push(f);
+        });
+        html.push_str(&escape(&span.text).replace('\n', "<br>"));
+    }
+    open.iter().rev().for_each(|f| html.push_str(close_tag(f)));
+
+    html
+}
+
+fn open_tag(format: &FormatValue) -> String {
+    match format {
+        FormatValue::Bold(_) => String::from("<strong>"),
+        FormatValue::Italic(_) => String::from("<em>"),
+        FormatValue::StrikeThrough(_) => String::from("<s>"),
+        FormatValue::Small(_) => String::from("<small>"),
+        FormatValue::Link(l) => format!("<a href=\"{}\">", escape(l.url.as_str())),
+        FormatValue::Mention(m) => match m.blog.blog_url() {
+            Some(href) => format!(
+                "<a class=\"npf-mention\" href=\"{}\">",
+                escape(href.as_str())
+            ),
+            None => String::from("<a class=\"npf-mention\">"),
+        },
+        FormatValue::Color(c) => format!("<span style=\"color: {}\">", escape(&c.hex)),
+    }
+}
+
+fn close_tag(format: &FormatValue) -> &'static str {
+    match format {
+        FormatValue::Bold(_) => "</strong>",
+        FormatValue::Italic(_) => "</em>",
+        FormatValue::StrikeThrough(_) => "</s>",
+        FormatValue::Small(_) => "</small>",
+        FormatValue::Link(_) | FormatValue::Mention(_) => "</a>",
+        FormatValue::Color(_) => "</span>",
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ListKind {
+    Ordered,
+    Unordered,
+}
+impl ListKind {
+    fn tag(&self) -> &'static str {
+        match self {
+            ListKind::Ordered => "ol",
+            ListKind::Unordered => "ul",
+        }
+    }
+}
+
+fn list_kind(text: &BlockText) -> Option<ListKind> {
+    match text.subtype {
+        Some(BlockTextSubtype::OrderedListItem) => Some(ListKind::Ordered),
+        Some(BlockTextSubtype::UnordoredListItem) => Some(ListKind::Unordered),
+        _ => None,
+    }
+}
+
+/// Groups consecutive list item blocks into nested lists, using their
+/// `ident_level`. Every open list also has an open `<li>`, so nested lists
+/// end up inside their parent item.
+#[derive(Default)]
+struct Lists {
+    stack: Vec<ListKind>,
+}
+impl Lists {
+    fn item(&mut self, text: &BlockText) -> String {
+        let mut html = String::new();
+        let kind = list_kind(text).unwrap_or(ListKind::Unordered);
+        let depth = text.ident_level.unwrap_or(0) as usize + 1;
+
+        while self.stack.len() > depth {
+            html.push_str(&self.close());
+        }
+        if self.stack.len() == depth {
+            if self.stack.last() == Some(&kind) {
+                html.push_str("</li>\n");
+            } else {
+                html.push_str(&self.close());
+            }
+        }
+        while self.stack.len() < depth {
+            let _ = writeln!(html, "<{}>", kind.tag());
+            self.stack.push(kind);
+        }
+
+        let _ = write!(html, "<li>{}", render_text(text));
+        html
+    }
+    fn close(&mut self) -> String {
+        match self.stack.pop() {
+            Some(kind) => format!("</li>\n</{}>\n", kind.tag()),
+            None => String::new(),
+        }
+    }
+    fn close_all(&mut self) -> String {
+        let mut html = String::new();
+        while !self.stack.is_empty() {
+            html.push_str(&self.close());
+        }
+        html
+    }
+}
+
+fn escape(string: &str) -> String {
+    string
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use comrak::Arena;
+
+    use crate::convert::npf;
+    use crate::convert::npf::objects::Post;
+    use crate::utils;
+
+    #[test]
+    fn formatting_nested() {
+        let markdown = "Hello [world, *this is*](https://guz.one) ~~a test~~.";
+        let arena = Arena::new();
+        let ast = comrak::parse_document(&arena, markdown, &utils::default_options());
+
+        let html = npf::html::render(&npf::from(ast).unwrap());
+
+        assert!(html.contains(
+            "<p>Hello <a href=\"https://guz.one/\">world, <em>this is</em></a> <s>a test</s>.</p>"
+        ));
+    }
+
+    #[test]
+    fn api_json() {
+        let json = r#"{
+            "object_type": "post",
+            "id": 1,
+            "id_string": "1",
+            "content": [
+                {"type": "text", "subtype": "heading1", "text": "Title"},
+                {"type": "text", "text": "small & italic",
+                    "formatting": [
+                        {"type": "small", "start": 0, "end": 5},
+                        {"type": "italic", "start": 8, "end": 14}
+                    ]},
+                {"type": "text", "subtype": "unordered-list-item", "text": "one"},
+                {"type": "text", "subtype": "unordered-list-item", "text": "nested", "indent_level": 1},
+                {"type": "text", "subtype": "ordered-list-item", "text": "two"}
+            ],
+            "layout": [],
+            "trail": []
+        }"#;
+        let post = serde_json::from_str::<Post>(json).unwrap();
+
+        let html = npf::html::render(&post);
+
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<p><small>small</small> &amp; <em>italic</em></p>"));
+        assert!(html.contains(
+            "<ul>\n<li>one<ul>\n<li>nested</li>\n</ul>\n</li>\n</ul>\n<ol>\n<li>two</li>\n</ol>"
+        ));
+    }
+
+    #[test]
+    fn rows_and_truncation() {
+        let json = r#"{
+            "object_type": "post",
+            "id": 1,
+            "id_string": "1",
+            "content": [
+                {"type": "image", "media": [{"url": "https://64.media.tumblr.com/a.png"}]},
+                {"type": "image", "media": [{"url": "https://64.media.tumblr.com/b.png"}]},
+                {"type": "text", "text": "Below the cut"}
+            ],
+            "layout": [{
+                "type": "rows",
+                "display": [{"blocks": [0, 1]}, {"blocks": [2]}],
+                "truncate_after": 1
+            }],
+            "trail": []
+        }"#;
+        let post = serde_json::from_str::<Post>(json).unwrap();
+
+        let html = npf::html::render(&post);
+
+        let row = html.find("<div class=\"npf-row\">").unwrap();
+        let cut = html.find("<summary>Keep reading</summary>").unwrap();
+        let text = html.find("<p>Below the cut</p>").unwrap();
+        assert!(row < cut && cut < text);
+        assert_eq!(html.matches("<figure class=\"npf-image\">").count(), 2);
+    }
+}
@@ -1,5 +1,53 @@
 use serde::{Deserialize, Serialize};
 
+/// Groups the indexes of `len` content blocks into the rows they are
+/// displayed in. Blocks left out of the layout are still shown, each on its
+/// own row after the laid out ones.
+pub fn rows(len: usize, layout: &[BlockValue]) -> Vec<Vec<usize>> {
+    let mut rows = layout
+        .iter()
+        .find_map(|l| match l {
+            BlockValue::Rows(r) => Some(&r.display),
+            _ => None,
+        })
+        .map(|d| {
+            d.iter()
+                .map(|d| {
+                    d.blocks
+                        .iter()
+                        .map(|b| *b as usize)
+                        .filter(|b| *b < len)
+                        .collect::<Vec<_>>()
+                })
+                .filter(|r| !r.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let missing = (0..len)
+        .filter(|i| !rows.iter().flatten().any(|b| b == i))
+        .map(|i| vec![i])
+        .collect::<Vec<_>>();
+    rows.extend(missing);
+    rows
+}
+
+/// Index of the last content block shown before the "Keep reading" cut.
+pub fn truncate_after(layout: &[BlockValue]) -> Option<usize> {
+    layout.iter().find_map(|l| match l {
+        BlockValue::Rows(r) => r.truncate_after.map(|t| t as usize),
+        _ => None,
+    })
+}
+
+pub fn ask(layout: &[BlockValue]) -> Option<&BlockAsk> {
+    layout.iter().find_map(|l| match l {
+        BlockValue::Ask(a) => Some(a),
+        _ => None,
+    })
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(untagged)]
 pub enum BlockValue {
@@ -78,8 +126,8 @@ impl From<u64> for DisplayBlocks {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BlockAsk {
     r#type: String,
-    blocks: Vec<u64>,
-    attribution: Option<super::attributions::AttributionBlog>,
+    pub blocks: Vec<u64>,
+    pub attribution: Option<super::attributions::AttributionBlog>,
 }
 impl BlockAsk {
     pub fn new(blocks: Vec<u64>) -> Self {
@@ -17,6 +17,15 @@ impl BlogInfo {
     pub fn is_valid(&self) -> bool {
         !self.uuid.is_empty() || !self.uuid.chars().count() == 22 || !self.uuid.starts_with("t:")
     }
+    /// The blog's URL, falling back to its tumblr.com address if only the
+    /// name is known.
+    pub fn blog_url(&self) -> Option<url::Url> {
+        self.url.clone().or_else(|| {
+            self.name
+                .as_ref()
+                .and_then(|n| url::Url::parse(&format!("https://{}.tumblr.com/", n)).ok())
+        })
+    }
     fn default() -> Self {
         Self {
             uuid: String::new(),
@@ -95,6 +104,14 @@ impl Media {
     pub fn is_valid(&self) -> bool {
         self.url.is_some() || self.identifier.is_some()
     }
+    /// Where the media can be loaded from: its URL, or the identifier (a
+    /// local file name) for media that wasn't uploaded yet.
+    pub fn src(&self) -> Option<String> {
+        self.url
+            .as_ref()
+            .map(|u| u.to_string())
+            .or_else(|| self.identifier.clone())
+    }
     fn default() -> Self {
         Self {
             r#type: None,
@@ -110,11 +127,7 @@ impl Media {
 }
 impl From<String> for Media {
     fn from(value: String) -> Self {
-        let mime = if let Some(m) = mime_guess::from_path(&value).first() {
-            Some(Mime::from(m))
-        } else {
-            None
-        };
+        let mime = mime_guess::from_path(&value).first().map(Mime::from);
         Self {
             r#type: mime,
             identifier: Some(value),
@@ -129,11 +142,9 @@ impl From<&str> for Media {
 }
 impl From<url::Url> for Media {
     fn from(value: url::Url) -> Self {
-        let mime = if let Some(m) = mime_guess::from_path(&value.to_string()).first() {
-            Some(Mime::from(m))
-        } else {
-            None
-        };
+        let mime = mime_guess::from_path(value.to_string())
+            .first()
+            .map(Mime::from);
         Self {
             r#type: mime,
             url: Some(value),
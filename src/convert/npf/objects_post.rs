@@ -63,28 +63,25 @@ impl Post {
     pub fn fold_content(self) -> Self {
         self.join_content("")
     }
-    pub fn join_content<'a>(mut self, sep: &'a str) -> Self {
+    pub fn join_content(mut self, sep: &str) -> Self {
         // TODO: Some form of folding also the layout of the npf
-        let groups = self.content.iter_mut().group_by(|c| match c {
-            BlockValue::Text(_) => true,
-            _ => false,
-        });
+        let groups = self
+            .content
+            .iter_mut()
+            .group_by(|c| matches!(c, BlockValue::Text(_)));
         self.content = groups
             .into_iter()
-            .map(|a| {
-                if a.0 == true {
-                    vec![BlockValue::Text(
-                        a.1.fold(BlockText::new(&String::new()), |acc, c| {
-                            fold_text_block_with_sep(acc, c, &sep)
-                        }),
-                    )]
+            .flat_map(|a| {
+                if a.0 {
+                    vec![BlockValue::Text(a.1.fold(BlockText::new(""), |acc, c| {
+                        fold_text_block_with_sep(acc, c, sep)
+                    }))]
                 } else {
                     a.1.map(|c| c.to_owned()).collect::<Vec<_>>()
                 }
             })
-            .flatten()
             .collect::<Vec<_>>();
-        if self.content.len() > 0 {
+        if !self.content.is_empty() {
             let block = &mut self.content[0];
             if let BlockValue::Text(ref mut t) = block {
                 t.text = String::from(t.text.strip_suffix(sep).unwrap_or(&t.text));
@@ -157,7 +154,7 @@ impl From<u64> for Post {
     }
 }
 
-fn fold_text_block_with_sep<'a>(mut acc: BlockText, c: &mut BlockValue, sep: &'a str) -> BlockText {
+fn fold_text_block_with_sep(mut acc: BlockText, c: &mut BlockValue, sep: &str) -> BlockText {
     if let BlockValue::Text(t) = c {
         if let Some(ref mut f) = &mut t.formatting {
             let offset = acc.text.chars().count() as u64;
@@ -1,10 +1,10 @@
 use std::{ops::Range, str::FromStr};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Serialize};
 
 use super::objects;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone)]
 #[serde(untagged)]
 pub enum FormatValue {
     Bold(FormatTypeBold),
@@ -27,6 +27,52 @@ impl FormatValue {
             FormatValue::StrikeThrough(ref mut f) => f.offset(offset),
         }
     }
+    pub fn range(&self) -> Range<u64> {
+        match self {
+            FormatValue::Bold(f) => f.range(),
+            FormatValue::Italic(f) => f.range(),
+            FormatValue::Link(f) => f.range(),
+            FormatValue::Small(f) => f.range(),
+            FormatValue::Color(f) => f.range(),
+            FormatValue::Mention(f) => f.range(),
+            FormatValue::StrikeThrough(f) => f.range(),
+        }
+    }
+}
+// Bold, italic, strikethrough and small share the same shape, so an untagged
+// deserialization would read all of them as bold. Dispatch on "type" instead.
+impl<'de> Deserialize<'de> for FormatValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let result = match value.get("type").and_then(|t| t.as_str()) {
+            Some("bold") => serde_json::from_value(value).map(FormatValue::Bold),
+            Some("italic") => serde_json::from_value(value).map(FormatValue::Italic),
+            Some("strikethrough") => serde_json::from_value(value).map(FormatValue::StrikeThrough),
+            Some("small") => serde_json::from_value(value).map(FormatValue::Small),
+            Some("link") => serde_json::from_value(value).map(FormatValue::Link),
+            Some("mention") => serde_json::from_value(value).map(FormatValue::Mention),
+            Some("color") => serde_json::from_value(value).map(FormatValue::Color),
+            Some(t) => {
+                return Err(de::Error::unknown_variant(
+                    t,
+                    &[
+                        "bold",
+                        "italic",
+                        "strikethrough",
+                        "small",
+                        "link",
+                        "mention",
+                        "color",
+                    ],
+                ))
+            }
+            None => return Err(de::Error::missing_field("type")),
+        };
+        result.map_err(de::Error::custom)
+    }
 }
 
 trait FormatType: From<Range<u64>> + From<String> {
@@ -103,7 +149,7 @@ impl FormatTypeColor {
 impl FormatType for FormatTypeColor {
     fn default() -> Self {
         Self {
-            r#type: String::from("link"),
+            r#type: String::from("color"),
             start: 0,
             end: 0,
             hex: String::from("#ffffff"),
@@ -156,6 +202,9 @@ macro_rules! ImplInlines {
                     self.start += offset;
                     self.end += offset;
                 }
+                pub fn range(&self) -> Range<u64> {
+                    self.start..self.end
+                }
             })*
         };
         // Defines the struct and implements Default trait if the token is an
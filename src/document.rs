@@ -0,0 +1,272 @@
+use std::string::FromUtf8Error;
+use std::sync::OnceLock;
+
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::Arena;
+
+use crate::config::Config;
+use crate::convert::npf::{self, objects, NPFConvertError};
+use crate::frontmatter::{self, Frontmatter, FrontmatterErr};
+use crate::{links, utils};
+
+#[derive(Debug)]
+pub enum DocumentErr {
+    Frontmatter(FrontmatterErr),
+    Convert(NPFConvertError),
+    Format(std::io::Error),
+    Utf8(FromUtf8Error),
+}
+impl From<FrontmatterErr> for DocumentErr {
+    fn from(value: FrontmatterErr) -> Self {
+        Self::Frontmatter(value)
+    }
+}
+impl From<NPFConvertError> for DocumentErr {
+    fn from(value: NPFConvertError) -> Self {
+        Self::Convert(value)
+    }
+}
+
+/// A Markdown document that owns its source, so it can be used without
+/// handling comrak's arena and nodes. The source is parsed once, on the first
+/// read, and mutations store the re-rendered Markdown.
+#[derive(Debug, Clone)]
+pub struct Document {
+    source: String,
+    options: comrak::Options,
+    npf: npf::Options,
+    parsed: OnceLock<Parsed>,
+    converted: OnceLock<Result<objects::Post, NPFConvertError>>,
+}
+
+// Documents can be shared between threads, like behind an `Arc`
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Document>();
+};
+
+/// What the reads return, collected from a single parse of the source.
+#[derive(Debug, Clone)]
+struct Parsed {
+    links: Vec<String>,
+    link_infos: Vec<links::LinkInfo>,
+    wikilinks: Vec<String>,
+    images: Vec<String>,
+    image_infos: Vec<links::ImageInfo>,
+    frontmatter: Option<String>,
+}
+
+impl Document {
+    pub fn parse(source: &str) -> Self {
+        Self::with_options(source, utils::default_options())
+    }
+    pub fn with_options(source: &str, options: comrak::Options) -> Self {
+        Self {
+            source: String::from(source),
+            options,
+            npf: npf::Options::default(),
+            parsed: OnceLock::new(),
+            converted: OnceLock::new(),
+        }
+    }
+    /// Parses with the extensions of the configuration, and converts with its
+    /// NPF options, like the CLI does.
+    pub fn with_config(source: &str, config: &Config) -> Self {
+        Self {
+            npf: config.npf.clone(),
+            ..Self::with_options(source, config.options())
+        }
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn links(&self) -> Vec<String> {
+        self.parsed().links.clone()
+    }
+    /// Links with their text, kind and position, see [`links::list_links`].
+    pub fn list_links(&self) -> Vec<links::LinkInfo> {
+        self.parsed().link_infos.clone()
+    }
+    pub fn replace_links(&mut self, from: &str, to: &str) -> Result<(), DocumentErr> {
+        self.edit(|_, ast| {
+            links::replace_links(ast, from, to);
+            Ok(())
+        })
+    }
+    /// Rewrites links and wikilinks with [`links::rewrite::Rules`].
+    pub fn rewrite_links(&mut self, rules: &links::rewrite::Rules) -> Result<(), DocumentErr> {
+        self.edit(|_, ast| {
+            links::rewrite_links(ast, rules);
+            links::rewrite_wikilinks(ast, rules);
+            Ok(())
+        })
+    }
+    pub fn remove_link(&mut self, url: &str) -> Result<(), DocumentErr> {
+        self.edit(|_, ast| {
+            links::remove_link(ast, url);
+            Ok(())
+        })
+    }
+    pub fn wikilinks(&self) -> Vec<String> {
+        self.parsed().wikilinks.clone()
+    }
+    pub fn replace_wikilinks(&mut self, from: &str, to: &str) -> Result<(), DocumentErr> {
+        self.edit(|_, ast| {
+            links::replace_wikilinks(ast, from, to);
+            Ok(())
+        })
+    }
+    pub fn images(&self) -> Vec<String> {
+        self.parsed().images.clone()
+    }
+    pub fn list_images(&self) -> Vec<links::ImageInfo> {
+        self.parsed().image_infos.clone()
+    }
+    pub fn replace_images(&mut self, from: &str, to: &str) -> Result<(), DocumentErr> {
+        self.edit(|_, ast| {
+            links::replace_images(ast, from, to);
+            Ok(())
+        })
+    }
+    pub fn rewrite_images(&mut self, rules: &links::rewrite::Rules) -> Result<(), DocumentErr> {
+        self.edit(|_, ast| {
+            links::rewrite_images(ast, rules);
+            Ok(())
+        })
+    }
+    pub fn remove_image(&mut self, url: &str) -> Result<(), DocumentErr> {
+        self.edit(|_, ast| {
+            links::remove_image(ast, url);
+            Ok(())
+        })
+    }
+    pub fn frontmatter(&self) -> Result<Frontmatter, DocumentErr> {
+        match &self.parsed().frontmatter {
+            Some(f) => Ok(Frontmatter::parse(f)?),
+            None => Ok(Frontmatter::new()),
+        }
+    }
+    /// Replaces the document's frontmatter, adding a frontmatter block if the
+    /// document doesn't have one.
+    pub fn set_frontmatter(&mut self, frontmatter: &Frontmatter) -> Result<(), DocumentErr> {
+        self.edit(|arena, ast| {
+            frontmatter::prepend_empty(arena, ast);
+            Ok(frontmatter.insert_ast(ast)?)
+        })
+    }
+    pub fn to_npf(&self) -> Result<objects::Post, DocumentErr> {
+        let converted = self
+            .converted
+            .get_or_init(|| self.read(|_, ast| npf::from_with(ast, &self.npf)));
+        Ok(converted.clone()?)
+    }
+    pub fn to_markdown(&self) -> String {
+        self.source.clone()
+    }
+
+    fn parsed(&self) -> &Parsed {
+        self.parsed.get_or_init(|| {
+            self.read(|_, ast| Parsed {
+                links: links::get_links(ast),
+                link_infos: links::list_links(ast, &self.source),
+                wikilinks: links::get_wikilinks(ast),
+                images: links::get_images(ast),
+                image_infos: links::list_images(ast, &self.source),
+                frontmatter: ast
+                    .descendants()
+                    .find_map(|n| match &n.data.borrow().value {
+                        NodeValue::FrontMatter(f) => Some(f.clone()),
+                        _ => None,
+                    }),
+            })
+        })
+    }
+    fn read<F, R>(&self, f: F) -> R
+    where
+        F: for<'a> FnOnce(&'a Arena<AstNode<'a>>, &'a AstNode<'a>) -> R,
+    {
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, &self.source, &self.options);
+        f(&arena, ast)
+    }
+    fn edit<F>(&mut self, f: F) -> Result<(), DocumentErr>
+    where
+        F: for<'a> FnOnce(&'a Arena<AstNode<'a>>, &'a AstNode<'a>) -> Result<(), DocumentErr>,
+    {
+        let options = self.options.clone();
+        self.source = self.read(|arena, ast| {
+            let references = links::references::snapshot(ast, &self.source);
+            f(arena, ast)?;
+            references.restore(arena);
+            utils::literal_wikilinks(ast);
+            let mut markdown = vec![];
+            comrak::format_commonmark(ast, &options, &mut markdown).map_err(DocumentErr::Format)?;
+            String::from_utf8(markdown).map_err(DocumentErr::Utf8)
+        })?;
+        self.parsed = OnceLock::new();
+        self.converted = OnceLock::new();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Document;
+    use crate::config::Config;
+
+    #[test]
+    fn links_and_frontmatter() {
+        let mut document = Document::parse(
+            "# Test string\n\
+            A [small](https://guz.one) phrase for [testing](https://example.com)",
+        );
+
+        assert_eq!(
+            document.links(),
+            vec!["https://guz.one", "https://example.com"]
+        );
+
+        document
+            .replace_links("https://guz.one", "https://guz.tumblr.com")
+            .unwrap();
+        document.remove_link("https://example.com").unwrap();
+
+        let mut frontmatter = document.frontmatter().unwrap();
+        frontmatter.insert(
+            String::from("title"),
+            serde_yaml::to_value("Test string").unwrap(),
+        );
+        document.set_frontmatter(&frontmatter).unwrap();
+
+        assert_eq!(
+            document.to_markdown(),
+            "---\n\
+            title: Test string\n\
+            ---\n\
+            \n\
+            # Test string\n\
+            \n\
+            A [small](https://guz.tumblr.com) phrase for testing\n"
+        );
+        assert_eq!(
+            document.frontmatter().unwrap().get(String::from("title")),
+            Some(&serde_yaml::to_value("Test string").unwrap())
+        );
+    }
+
+    #[test]
+    fn options_from_config() {
+        let source = "See https://guz.one and [[Page]].\n";
+        assert_eq!(Document::parse(source).links(), Vec::<String>::new());
+
+        let mut config = Config::default();
+        config.extensions.autolink = true;
+        config.extensions.wikilinks = true;
+        config.npf.wikilinks.pattern = Some(String::from("https://guz.one/{slug}"));
+        let document = Document::with_config(source, &config);
+
+        assert_eq!(document.links(), vec!["https://guz.one"]);
+        assert_eq!(document.wikilinks(), vec!["Page"]);
+        assert!(document.to_npf().is_ok());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files treated as Markdown.
+pub const MARKDOWN_EXTENSIONS: [&str; 3] = ["md", "markdown", "mdown"];
+
+#[derive(Debug)]
+pub enum FilesErr {
+    Pattern(glob::PatternError),
+    Io { path: PathBuf, err: std::io::Error },
+    NotFound(String),
+}
+
+/// Which files found by walking directories or expanding globs are kept.
+/// Files given explicitly by path are always kept.
+#[derive(Debug, Default)]
+pub struct Filter {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+}
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, FilesErr> {
+        let patterns = |p: &[String]| {
+            p.iter()
+                .map(|p| glob::Pattern::new(p).map_err(FilesErr::Pattern))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            include: patterns(include)?,
+            exclude: patterns(exclude)?,
+        })
+    }
+    /// Without include patterns, files found in directories are only kept if
+    /// they have a Markdown extension.
+    fn matches(&self, path: &Path, walked: bool) -> bool {
+        let matches = |p: &glob::Pattern| {
+            p.matches_path(path)
+                || path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| p.matches(n))
+        };
+        let included = if !self.include.is_empty() {
+            self.include.iter().any(matches)
+        } else if walked {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| MARKDOWN_EXTENSIONS.contains(&e))
+        } else {
+            true
+        };
+        included && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Expands the inputs into the list of files to process. Each input can be a
+/// path to a file, a directory (walked recursively if `recursive` is set) or
+/// a glob pattern. `-`, for stdin, is passed through.
+pub fn discover(
+    inputs: &[String],
+    recursive: bool,
+    filter: &Filter,
+) -> Result<Vec<PathBuf>, FilesErr> {
+    let mut files = vec![];
+
+    for input in inputs {
+        let path = PathBuf::from(input);
+        if input == "-" || path.is_file() {
+            files.push(path);
+        } else if path.is_dir() {
+            files.append(&mut walk(&path, recursive, filter)?);
+        } else {
+            let mut matches = vec![];
+            for entry in glob::glob(input).map_err(FilesErr::Pattern)? {
+                let path = entry.map_err(|e| FilesErr::Io {
+                    path: e.path().to_path_buf(),
+                    err: e.into(),
+                })?;
+                if path.is_dir() {
+                    matches.append(&mut walk(&path, recursive, filter)?);
+                } else if filter.matches(&path, false) {
+                    matches.push(path);
+                }
+            }
+            if matches.is_empty() {
+                return Err(FilesErr::NotFound(input.clone()));
+            }
+            files.append(&mut matches);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    files.retain(|f| seen.insert(f.clone()));
+    Ok(files)
+}
+
+fn walk(dir: &Path, recursive: bool, filter: &Filter) -> Result<Vec<PathBuf>, FilesErr> {
+    let io_err = |err| FilesErr::Io {
+        path: dir.to_path_buf(),
+        err,
+    };
+
+    let mut entries = std::fs::read_dir(dir)
+        .map_err(io_err)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_err)?;
+    entries.sort();
+
+    let mut files = vec![];
+    for path in entries {
+        // Hidden files and directories, like .git, are skipped
+        if path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            if recursive {
+                files.append(&mut walk(&path, recursive, filter)?);
+            }
+        } else if filter.matches(&path, true) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Replaces the contents of the file at `path`, writing to a temporary file
+/// in the same directory and renaming it over the original, so the file is
+/// never left partially written. The original permissions are kept, and if
+/// `backup` is set the previous contents are copied to `<path>.bak`. Symlinks
+/// are followed, replacing the file they point to and keeping the link.
+///
+/// Nothing is written, and `false` is returned, if the contents are unchanged.
+pub fn write(path: &Path, contents: &str, backup: bool) -> Result<bool, FilesErr> {
+    let io_err = |err| FilesErr::Io {
+        path: path.to_path_buf(),
+        err,
+    };
+
+    let metadata = match std::fs::metadata(path) {
+        Ok(m) => Some(m),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(io_err(e)),
+    };
+    if metadata.is_some() && std::fs::read(path).map_err(io_err)? == contents.as_bytes() {
+        return Ok(false);
+    }
+
+    let target = match metadata {
+        Some(_) => std::fs::canonicalize(path).map_err(io_err)?,
+        None => path.to_path_buf(),
+    };
+    let name = target
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let tmp = target.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    let tmp_err = |err| FilesErr::Io {
+        path: tmp.clone(),
+        err,
+    };
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp).map_err(tmp_err)?;
+        file.write_all(contents.as_bytes()).map_err(tmp_err)?;
+        file.sync_all().map_err(tmp_err)?;
+        if let Some(metadata) = &metadata {
+            std::fs::set_permissions(&tmp, metadata.permissions()).map_err(tmp_err)?;
+            if backup {
+                let mut bak = path.as_os_str().to_owned();
+                bak.push(".bak");
+                std::fs::copy(path, &bak).map_err(|err| FilesErr::Io {
+                    path: PathBuf::from(bak),
+                    err,
+                })?;
+            }
+        }
+        std::fs::rename(&tmp, &target).map_err(io_err)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result.map(|_| true)
+}
+
+/// Unified diff between the `old` and `new` contents of the file `name`,
+/// empty if they are equal.
+pub fn diff(name: &str, old: &str, new: &str) -> String {
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(name, name)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::{diff, discover, write, Filter};
+
+    #[test]
+    fn discover_files() {
+        let dir = std::env::temp_dir().join(format!("mdparser-discover-{}", std::process::id()));
+        fs::create_dir_all(dir.join("posts/drafts")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        [
+            "a.md",
+            "b.txt",
+            "posts/c.md",
+            "posts/drafts/d.markdown",
+            ".git/e.md",
+        ]
+        .iter()
+        .for_each(|f| fs::write(dir.join(f), "").unwrap());
+
+        let root = dir.to_str().unwrap().to_string();
+        let relative = |files: Vec<PathBuf>| {
+            files
+                .iter()
+                .map(|f| f.strip_prefix(&dir).unwrap().to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let files = discover(std::slice::from_ref(&root), false, &Filter::default()).unwrap();
+        assert_eq!(relative(files), vec!["a.md"]);
+
+        let files = discover(std::slice::from_ref(&root), true, &Filter::default()).unwrap();
+        assert_eq!(
+            relative(files),
+            vec!["a.md", "posts/c.md", "posts/drafts/d.markdown"]
+        );
+
+        let filter = Filter::new(&[], &[String::from("**/drafts/*")]).unwrap();
+        let files = discover(std::slice::from_ref(&root), true, &filter).unwrap();
+        assert_eq!(relative(files), vec!["a.md", "posts/c.md"]);
+
+        let files = discover(
+            &[format!("{}/*.txt", root), format!("{}/a.md", root)],
+            false,
+            &Filter::default(),
+        )
+        .unwrap();
+        assert_eq!(relative(files), vec!["b.txt", "a.md"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_in_place() {
+        let dir = std::env::temp_dir().join(format!("mdparser-write-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("post.md");
+        fs::write(&path, "A longer original text\n").unwrap();
+
+        assert!(write(&path, "Shorter\n", true).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Shorter\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("post.md.bak")).unwrap(),
+            "A longer original text\n"
+        );
+
+        assert!(!write(&path, "Shorter\n", false).unwrap());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        #[cfg(unix)]
+        {
+            let link = dir.join("link.md");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            assert!(write(&link, "Through the link\n", false).unwrap());
+            assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+            assert_eq!(fs::read_to_string(&path).unwrap(), "Through the link\n");
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unified_diff() {
+        assert_eq!(diff("post.md", "a\nb\n", "a\nb\n"), "");
+        assert_eq!(
+            diff("post.md", "a\nb\n", "a\nc\n"),
+            "--- post.md\n+++ post.md\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n"
+        );
+    }
+}
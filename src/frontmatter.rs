@@ -1,65 +1,408 @@
+use std::cell::RefCell;
 use std::fmt::Display;
+use std::sync::LazyLock;
 
 use serde_yaml as yaml;
-use std::collections::HashMap;
 
-use comrak::nodes::{AstNode, NodeValue};
+use comrak::arena_tree::Node;
+use comrak::nodes::{Ast, AstNode, LineColumn, NodeValue};
+use comrak::Arena;
+
+pub mod compute;
+pub mod format;
+pub mod merge;
+pub mod path;
+pub mod query;
+pub mod schema;
+
+use format::Format;
+use path::{Path, PathErr};
 
 #[derive(Debug)]
 pub enum FrontmatterErr {
     InvalidFrontmatter,
     Parsing(yaml::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    /// The values can't be written in the format, like nulls in TOML
+    Serializing {
+        format: Format,
+        description: String,
+    },
+    Path(PathErr),
 }
 
-#[derive(Debug)]
+/// A key at the start of a line, with its indentation, in any of the formats.
+static KEY: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r#"^(\s*)(?:\[+\s*)?["']?([^"'\s=:\[\].]+)["']?\s*[:=\].]"#).unwrap()
+});
+
+/// The frontmatter of a document, in YAML, TOML or JSON. Keys keep their
+/// order, and when written back in the same format, the keys that weren't
+/// changed keep their original text, with their comments and formatting.
+#[derive(Debug, Clone)]
 pub struct Frontmatter {
-    map: HashMap<String, yaml::Value>,
+    map: yaml::Mapping,
+    format: Format,
+    /// JSON written as a bare object, without `;;;` delimiters
+    bare: bool,
+    source: Option<Source>,
+    /// The text it was parsed from, with its delimiters
+    text: String,
 }
 impl<'a> Frontmatter {
     pub fn new() -> Self {
         Self {
-            map: HashMap::new(),
+            map: yaml::Mapping::new(),
+            format: Format::Yaml,
+            bare: false,
+            source: None,
+            text: String::new(),
         }
     }
-    pub fn parse(string: &'a str) -> Result<HashMap<String, yaml::Value>, FrontmatterErr> {
-        let mut string = string.trim();
-        string = match string.strip_prefix("---") {
-            Some(s) => s,
-            None => return Err(FrontmatterErr::InvalidFrontmatter),
-        };
-        string = match string.strip_suffix("---") {
-            Some(s) => s,
+    pub fn parse(string: &'a str) -> Result<Self, FrontmatterErr> {
+        let string = string.trim();
+        let (format, bare, body) = match Format::delimited(string) {
+            Some(format) => {
+                let body = string[format.delimiter().len()..]
+                    .strip_suffix(format.delimiter())
+                    .ok_or(FrontmatterErr::InvalidFrontmatter)?;
+                let body = body.strip_prefix("\r\n").unwrap_or(body);
+                (format, false, body.strip_prefix('\n').unwrap_or(body))
+            }
+            None if string.starts_with('{') => (Format::Json, true, string),
             None => return Err(FrontmatterErr::InvalidFrontmatter),
         };
-        string = string.trim();
-        yaml::from_str(string).map_err(|e| FrontmatterErr::Parsing(e))
+
+        let map = format.parse(body)?;
+        Ok(Self {
+            source: Source::split(body, &map, format),
+            map,
+            format,
+            bare,
+            text: String::from(string),
+        })
+    }
+    pub fn format(&self) -> Format {
+        self.format
+    }
+    /// Line of a top-level key in the text the frontmatter was parsed from,
+    /// where the opening delimiter is line 1.
+    pub fn line(&self, key: &str) -> Option<usize> {
+        let keys = self
+            .text
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let captures = KEY.captures(line)?;
+                Some((i + 1, captures[1].len(), captures.get(2)?.as_str()))
+            })
+            .collect::<Vec<_>>();
+        // Nested keys are indented, like in YAML and JSON
+        let indent = keys.iter().map(|(_, indent, _)| *indent).min()?;
+        keys.into_iter()
+            .find(|(_, i, k)| *i == indent && *k == key)
+            .map(|(line, ..)| line)
+    }
+    /// Changes the format the frontmatter is written in. JSON is written as
+    /// a bare object.
+    pub fn convert(&mut self, format: Format) -> Result<(), FrontmatterErr> {
+        format.serialize(&self.map)?;
+        if format != self.format {
+            self.format = format;
+            self.bare = format == Format::Json;
+            self.source = None;
+        }
+        Ok(())
     }
     pub fn insert(&mut self, key: String, value: yaml::Value) {
-        self.map.insert(key, value);
+        self.map.insert(yaml::Value::String(key), value);
     }
     pub fn remove(&mut self, key: String) -> Option<yaml::Value> {
-        self.map.remove(&key)
+        self.map.shift_remove(key.as_str())
     }
     pub fn get(&self, key: String) -> Option<&yaml::Value> {
-        self.map.get(&key)
+        self.map.get(key.as_str())
+    }
+    /// The value at a path like `author.name` or `tags[0]`, see [`Path`].
+    pub fn get_path(&self, path: &str) -> Result<Option<&yaml::Value>, FrontmatterErr> {
+        let path = Path::parse(path).map_err(FrontmatterErr::Path)?;
+        let (first, rest) = path.0.split_first().expect("paths aren't empty");
+        let value = match first {
+            path::Segment::Key(key) => self.map.get(key.as_str()),
+            path::Segment::Index(_) => None,
+        };
+        Ok(value.and_then(|v| path::get(v, rest)))
+    }
+    /// Sets the value at a path, creating the mappings and sequences before
+    /// it.
+    pub fn set_path(&mut self, path: &str, value: yaml::Value) -> Result<(), FrontmatterErr> {
+        let path = Path::parse(path).map_err(FrontmatterErr::Path)?;
+        self.with_root(|root| path::set(root, &path, value))
+            .map_err(FrontmatterErr::Path)
+    }
+    pub fn remove_path(&mut self, path: &str) -> Result<Option<yaml::Value>, FrontmatterErr> {
+        let path = Path::parse(path).map_err(FrontmatterErr::Path)?;
+        Ok(self.with_root(|root| path::remove(root, &path.0)))
+    }
+    /// Adds items to the end of the sequence at a path, creating it when it's
+    /// missing. With `unique`, items already in the sequence are skipped.
+    pub fn append_path(
+        &mut self,
+        path: &str,
+        items: Vec<yaml::Value>,
+        unique: bool,
+    ) -> Result<(), FrontmatterErr> {
+        let path = Path::parse(path).map_err(FrontmatterErr::Path)?;
+        self.with_root(|root| {
+            let sequence = match path::get_mut(root, &path.0) {
+                Some(yaml::Value::Sequence(s)) => s,
+                None | Some(yaml::Value::Null) => {
+                    path::set(root, &path, yaml::Value::Sequence(vec![]))?;
+                    match path::get_mut(root, &path.0) {
+                        Some(yaml::Value::Sequence(s)) => s,
+                        _ => unreachable!("the sequence was just set"),
+                    }
+                }
+                Some(_) => return Err(PathErr::NotASequence(path.to_string())),
+            };
+            for item in items {
+                if !(unique && sequence.contains(&item)) {
+                    sequence.push(item);
+                }
+            }
+            Ok(())
+        })
+        .map_err(FrontmatterErr::Path)
+    }
+    /// Removes every item equal to one of `items` from the sequence at a
+    /// path, returning how many were removed.
+    pub fn remove_items_path(
+        &mut self,
+        path: &str,
+        items: &[yaml::Value],
+    ) -> Result<usize, FrontmatterErr> {
+        let path = Path::parse(path).map_err(FrontmatterErr::Path)?;
+        self.with_root(|root| match path::get_mut(root, &path.0) {
+            Some(yaml::Value::Sequence(s)) => {
+                let len = s.len();
+                s.retain(|v| !items.contains(v));
+                Ok(len - s.len())
+            }
+            None | Some(yaml::Value::Null) => Ok(0),
+            Some(_) => Err(PathErr::NotASequence(path.to_string())),
+        })
+        .map_err(FrontmatterErr::Path)
+    }
+    /// Flips the boolean at a path, setting it to `true` when it's missing,
+    /// and returns its new value.
+    pub fn toggle_path(&mut self, path: &str) -> Result<bool, FrontmatterErr> {
+        let path = Path::parse(path).map_err(FrontmatterErr::Path)?;
+        self.with_root(|root| {
+            let value = match path::get(root, &path.0) {
+                Some(yaml::Value::Bool(b)) => !b,
+                None | Some(yaml::Value::Null) => true,
+                Some(_) => return Err(PathErr::NotABoolean(path.to_string())),
+            };
+            path::set(root, &path, yaml::Value::Bool(value))?;
+            Ok(value)
+        })
+        .map_err(FrontmatterErr::Path)
     }
     pub fn extend<T>(&mut self, iter: T)
     where
-        T: IntoIterator<Item = (String, yaml::Value)>,
+        T: IntoIterator<Item = (yaml::Value, yaml::Value)>,
     {
         self.map.extend(iter)
     }
-    pub fn insert_ast(&self, ast: &'a AstNode<'a>) {
+    /// Inserts the values, combining them with the existing ones by the
+    /// strategy.
+    pub fn merge(&mut self, values: yaml::Mapping, strategy: merge::Strategy) {
+        for (key, value) in values {
+            match self.map.get_mut(&key) {
+                Some(existing) => merge::merge(existing, value, strategy),
+                None => {
+                    self.map.insert(key, value);
+                }
+            }
+        }
+    }
+    /// Replaces the frontmatter node of `ast` with this frontmatter, failing
+    /// if it can't be written in its format.
+    pub fn insert_ast(&self, ast: &'a AstNode<'a>) -> Result<(), FrontmatterErr> {
         if let NodeValue::FrontMatter(ref mut f) = &mut ast.data.borrow_mut().value {
-            *f = self.to_string();
+            *f = self.serialize()?;
         } else {
             for c in ast.children() {
-                self.insert_ast(c)
+                self.insert_ast(c)?;
             }
         }
+        Ok(())
+    }
+    /// The frontmatter with its delimiters, as it is written in the document.
+    pub fn serialize(&self) -> Result<String, FrontmatterErr> {
+        let source = self.source.as_ref();
+        let string = match source.and_then(|s| s.render(&self.map, self.format)) {
+            Some(s) => s,
+            None => self.format.serialize(&self.map)?,
+        };
+        if self.bare {
+            Ok(format!("{}\n", string))
+        } else {
+            let delimiter = self.format.delimiter();
+            Ok(format!("{}\n{}{}\n\n", delimiter, string, delimiter))
+        }
     }
     pub fn to_value(&self) -> Result<yaml::Value, yaml::Error> {
-        yaml::to_value(&self.map)
+        Ok(yaml::Value::Mapping(self.map.clone()))
+    }
+
+    /// Runs `f` with the frontmatter as a YAML mapping value, for the
+    /// functions of [`path`].
+    fn with_root<R>(&mut self, f: impl FnOnce(&mut yaml::Value) -> R) -> R {
+        let mut root = yaml::Value::Mapping(std::mem::take(&mut self.map));
+        let result = f(&mut root);
+        if let yaml::Value::Mapping(m) = root {
+            self.map = m;
+        }
+        result
+    }
+}
+
+/// The text a frontmatter was parsed from, split in the lines of each key.
+#[derive(Debug, Clone)]
+struct Source {
+    blocks: Vec<Block>,
+    /// Comments and blank lines after the last key
+    trailer: String,
+    original: yaml::Mapping,
+}
+#[derive(Debug, Clone)]
+struct Block {
+    key: yaml::Value,
+    /// Comments and blank lines before the key
+    comments: String,
+    text: String,
+}
+impl Source {
+    /// Splits the YAML or TOML in the lines of each top-level key, or `None`
+    /// if they can't be told apart, like in a flow mapping or JSON.
+    fn split(body: &str, original: &yaml::Mapping, format: Format) -> Option<Self> {
+        if format == Format::Json {
+            return None;
+        }
+        let mut blocks: Vec<Block> = vec![];
+        let mut pending = String::new();
+        // Keys after a TOML table header are part of the table
+        let mut in_table = false;
+        for line in body.split_inclusive('\n') {
+            let line = if line.ends_with('\n') {
+                String::from(line)
+            } else {
+                format!("{}\n", line)
+            };
+            let is_header = format == Format::Toml && line.starts_with('[');
+            in_table |= is_header;
+            let is_key = is_header
+                || !in_table
+                    && line.starts_with(|c: char| {
+                        !c.is_whitespace() && !matches!(c, '#' | '-' | ']' | '}' | ',')
+                    });
+            let is_comment = line.trim().is_empty() || line.starts_with('#');
+            match blocks.last_mut() {
+                _ if is_key => blocks.push(Block {
+                    key: yaml::Value::Null,
+                    comments: std::mem::take(&mut pending),
+                    text: line,
+                }),
+                Some(_) if is_comment => pending.push_str(&line),
+                Some(block) => {
+                    block.text.push_str(&std::mem::take(&mut pending));
+                    block.text.push_str(&line);
+                }
+                None => pending.push_str(&line),
+            }
+        }
+
+        for block in &mut blocks {
+            let mapping = format.parse(&block.text).ok()?;
+            let (key, _) = mapping.into_iter().next()?;
+            block.key = key;
+        }
+        if !blocks.iter().map(|b| &b.key).eq(original.keys()) {
+            return None;
+        }
+        Some(Self {
+            blocks,
+            trailer: pending,
+            original: original.clone(),
+        })
+    }
+
+    /// The text of `map`, keeping the text of the keys that didn't change,
+    /// and adding new keys at the end. `None` if the keys can't be written
+    /// apart, like a TOML value added after a table.
+    fn render(&self, map: &yaml::Mapping, format: Format) -> Option<String> {
+        let added = map
+            .iter()
+            .filter(|(k, _)| !self.original.contains_key(*k))
+            .map(|(k, v)| entry(k, v, format))
+            .collect::<Option<Vec<_>>>()?;
+        // New TOML values go before the tables, so they aren't part of one
+        let (mut values, tables): (Vec<_>, Vec<_>) = added
+            .into_iter()
+            .partition(|e| format != Format::Toml || !e.starts_with('['));
+
+        let mut out = String::new();
+        for block in &self.blocks {
+            if format == Format::Toml && block.text.starts_with('[') {
+                values.drain(..).for_each(|e| out.push_str(&e));
+            }
+            match map.get(&block.key) {
+                Some(v) if self.original.get(&block.key) == Some(v) => {
+                    out.push_str(&block.comments);
+                    out.push_str(&block.text);
+                }
+                Some(v) => {
+                    out.push_str(&block.comments);
+                    out.push_str(&entry(&block.key, v, format)?);
+                }
+                None => {}
+            }
+        }
+        values
+            .into_iter()
+            .chain(tables)
+            .for_each(|e| out.push_str(&e));
+        out.push_str(&self.trailer);
+        format.parse(&out).is_ok_and(|m| m == *map).then_some(out)
+    }
+}
+
+fn entry(key: &yaml::Value, value: &yaml::Value, format: Format) -> Option<String> {
+    let mut mapping = yaml::Mapping::new();
+    mapping.insert(key.clone(), value.clone());
+    format.serialize(&mapping).ok()
+}
+
+/// Prepends an empty frontmatter block to the document, if it doesn't already
+/// have one, so it can be filled with [`Frontmatter::insert_ast`].
+pub fn prepend_empty<'a>(arena: &'a Arena<AstNode<'a>>, ast: &'a AstNode<'a>) {
+    if !ast
+        .children()
+        .any(|c| matches!(c.data.borrow().value, NodeValue::FrontMatter(_)))
+    {
+        let node = arena.alloc(Node::new(RefCell::from(Ast::new(
+            NodeValue::FrontMatter(String::from("---\n\n---")),
+            LineColumn { line: 0, column: 0 },
+        ))));
+        ast.prepend(node);
+    }
+}
+
+impl Default for Frontmatter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -67,18 +410,10 @@ impl<'a> TryFrom<&'a AstNode<'a>> for Frontmatter {
     type Error = FrontmatterErr;
     fn try_from(value: &'a AstNode<'a>) -> Result<Self, Self::Error> {
         if let NodeValue::FrontMatter(f) = &value.data.borrow().value {
-            return Ok(Frontmatter {
-                map: Frontmatter::parse(f)?,
-            });
+            return Frontmatter::parse(f);
         }
-        for node in value.children() {
-            if let NodeValue::FrontMatter(f) = &value.data.borrow().value {
-                return Ok(Frontmatter {
-                    map: Frontmatter::parse(f)?,
-                });
-            } else {
-                return Frontmatter::try_from(node);
-            }
+        if let Some(node) = value.children().next() {
+            return Frontmatter::try_from(node);
         }
         Ok(Frontmatter::new())
     }
@@ -86,11 +421,8 @@ impl<'a> TryFrom<&'a AstNode<'a>> for Frontmatter {
 
 impl Display for Frontmatter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let string = match yaml::to_string(&self.map) {
-            Ok(s) => s,
-            Err(_) => return Err(std::fmt::Error),
-        };
-        write!(f, "---\n{}---\n\n", string)
+        let string = self.serialize().map_err(|_| std::fmt::Error)?;
+        write!(f, "{}", string)
     }
 }
 
@@ -113,7 +445,7 @@ mod test {
                     A small phrase for testing y'know";
 
         let arena = Arena::new();
-        let ast = comrak::parse_document(&arena, &string, &utils::default_options());
+        let ast = comrak::parse_document(&arena, string, &utils::default_options());
 
         let mut frontmatter = Frontmatter::try_from(ast).unwrap();
 
@@ -132,13 +464,13 @@ mod test {
             Some(serde_yaml::to_value("another value").unwrap())
         );
 
-        frontmatter.insert_ast(ast);
+        frontmatter.insert_ast(ast).unwrap();
 
         let mut res = vec![];
         comrak::format_commonmark(ast, &utils::default_options(), &mut res).unwrap();
         let res = String::from_utf8(res).unwrap();
 
-        let slices = res.split("---").into_iter().collect::<Vec<&str>>();
+        let slices = res.split("---").collect::<Vec<&str>>();
         let f = slices[1];
 
         assert_eq!(
@@ -161,4 +493,73 @@ mod test {
             A small phrase for testing y'know\n"
         );
     }
+
+    #[test]
+    fn list_operations() {
+        let mut frontmatter = Frontmatter::parse("---\ntags: [art]\ntitle: Hi\n---").unwrap();
+        frontmatter
+            .append_path("tags", vec!["ink".into(), "art".into()], false)
+            .unwrap();
+        frontmatter
+            .append_path("extra.tags", vec!["pen".into(), "pen".into()], true)
+            .unwrap();
+        assert_eq!(
+            frontmatter
+                .remove_items_path("tags", &["art".into()])
+                .unwrap(),
+            2
+        );
+        assert!(frontmatter.append_path("title", vec![], false).is_err());
+        assert!(frontmatter.toggle_path("extra.draft").unwrap());
+        assert!(!frontmatter.toggle_path("extra.draft").unwrap());
+        assert!(frontmatter.toggle_path("title").is_err());
+
+        assert_eq!(
+            frontmatter.to_string(),
+            "---\n\
+            tags:\n\
+            - ink\n\
+            title: Hi\n\
+            extra:\n  \
+              tags:\n  \
+              - pen\n  \
+              draft: false\n\
+            ---\n\n"
+        );
+    }
+
+    #[test]
+    fn frontmatter_round_trip() {
+        let string = "---\n\
+            # Post settings\n\
+            title: 'Hello'   # shown on the page\n\
+            state: draft\n\
+            \n\
+            tags:\n\
+              - art\n\
+              - \"ink\"\n\
+            author: {name: Guz}\n\
+            # end\n\
+            ---\n";
+        let mut frontmatter = Frontmatter::parse(string).unwrap();
+        assert_eq!(frontmatter.to_string(), format!("{}\n", string));
+
+        frontmatter.set_path("state", "published".into()).unwrap();
+        frontmatter.remove(String::from("author"));
+        frontmatter.insert(String::from("date"), "2024-05-01".into());
+        assert_eq!(
+            frontmatter.to_string(),
+            "---\n\
+            # Post settings\n\
+            title: 'Hello'   # shown on the page\n\
+            state: published\n\
+            \n\
+            tags:\n\
+              - art\n\
+              - \"ink\"\n\
+            date: 2024-05-01\n\
+            # end\n\
+            ---\n\n"
+        );
+    }
 }
@@ -0,0 +1,310 @@
+use std::cell::RefCell;
+use std::time::SystemTime;
+
+use comrak::nodes::{AstNode, NodeValue};
+use serde::Deserialize;
+use serde_yaml as yaml;
+
+use super::Frontmatter;
+use crate::{links, utils};
+
+/// A field derived from the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Field {
+    /// Text of the first level 1 heading
+    Title,
+    WordCount,
+    /// Minutes to read the words, rounded up
+    ReadingTime,
+    /// Date the file was last modified
+    Updated,
+    /// Text of the first paragraph, shortened to the summary length
+    Summary,
+    /// URL of the first image
+    Image,
+    /// Number of links and wikilinks
+    LinksCount,
+}
+impl Field {
+    pub const ALL: [Field; 7] = [
+        Field::Title,
+        Field::WordCount,
+        Field::ReadingTime,
+        Field::Updated,
+        Field::Summary,
+        Field::Image,
+        Field::LinksCount,
+    ];
+}
+
+/// Keys the fields are written to. An empty key skips the field.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    pub title: String,
+    pub word_count: String,
+    pub reading_time: String,
+    pub updated: String,
+    pub summary: String,
+    pub image: String,
+    pub links_count: String,
+}
+impl Default for Keys {
+    fn default() -> Self {
+        Self {
+            title: String::from("title"),
+            word_count: String::from("word_count"),
+            reading_time: String::from("reading_time"),
+            updated: String::from("updated"),
+            summary: String::from("summary"),
+            image: String::from("image"),
+            links_count: String::from("links_count"),
+        }
+    }
+}
+impl Keys {
+    pub fn get(&self, field: Field) -> &str {
+        match field {
+            Field::Title => &self.title,
+            Field::WordCount => &self.word_count,
+            Field::ReadingTime => &self.reading_time,
+            Field::Updated => &self.updated,
+            Field::Summary => &self.summary,
+            Field::Image => &self.image,
+            Field::LinksCount => &self.links_count,
+        }
+    }
+}
+
+/// How `frontmatter compute` derives fields, usually set in the `[compute]`
+/// section of the project's configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Options {
+    pub keys: Keys,
+    pub words_per_minute: usize,
+    /// Characters the summary is shortened to, at a word boundary
+    pub summary_length: usize,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            keys: Keys::default(),
+            words_per_minute: 200,
+            summary_length: 200,
+        }
+    }
+}
+
+/// What is collected while walking the document.
+#[derive(Default)]
+struct Stats {
+    title: Option<String>,
+    words: usize,
+    summary: Option<String>,
+    image: Option<String>,
+    links: usize,
+}
+
+/// The fields of the document, without the ones it has nothing for, like a
+/// title without a level 1 heading. `modified` is the file's modification
+/// time, for [`Field::Updated`].
+pub fn compute<'a>(
+    ast: &'a AstNode<'a>,
+    modified: Option<SystemTime>,
+    options: &Options,
+) -> Vec<(Field, yaml::Value)> {
+    let stats = RefCell::new(Stats::default());
+    utils::iter_nodes(ast, &|node| {
+        let mut stats = stats.borrow_mut();
+        match &node.data.borrow().value {
+            NodeValue::Heading(h) if h.level == 1 && stats.title.is_none() => {
+                stats.title = Some(links::text(node));
+            }
+            NodeValue::Paragraph if stats.summary.is_none() && !only_images(node) => {
+                stats.summary = Some(links::text(node));
+            }
+            _ if in_image(node) => {}
+            NodeValue::Text(t) => stats.words += words(t),
+            NodeValue::Code(c) => stats.words += words(&c.literal),
+            NodeValue::Image(i) if stats.image.is_none() => stats.image = Some(i.url.clone()),
+            NodeValue::Link(_) | NodeValue::WikiLink(_) => stats.links += 1,
+            _ => {}
+        }
+    });
+    let stats = stats.into_inner();
+
+    let updated = modified
+        .and_then(|m| m.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| utils::format_date((d.as_secs() / 86400) as i64));
+    let reading_time = stats.words.div_ceil(options.words_per_minute.max(1));
+
+    Field::ALL
+        .into_iter()
+        .filter_map(|field| {
+            let value = match field {
+                Field::Title => yaml::Value::String(stats.title.clone()?),
+                Field::WordCount => stats.words.into(),
+                Field::ReadingTime => reading_time.into(),
+                Field::Updated => yaml::Value::String(updated.clone()?),
+                Field::Summary => {
+                    yaml::Value::String(shorten(stats.summary.as_deref()?, options.summary_length))
+                }
+                Field::Image => yaml::Value::String(stats.image.clone()?),
+                Field::LinksCount => stats.links.into(),
+            };
+            Some((field, value))
+        })
+        .collect()
+}
+
+/// Sets the computed `fields` in the frontmatter, keeping the values already
+/// there unless `overwrite` is set, and returns the ones set.
+pub fn apply<'a>(
+    frontmatter: &mut Frontmatter,
+    ast: &'a AstNode<'a>,
+    modified: Option<SystemTime>,
+    fields: &[Field],
+    overwrite: bool,
+    options: &Options,
+) -> Vec<Field> {
+    compute(ast, modified, options)
+        .into_iter()
+        .filter(|(field, _)| fields.contains(field))
+        .filter_map(|(field, value)| {
+            let key = options.keys.get(field);
+            let existing = frontmatter.get(String::from(key));
+            if key.is_empty() || !overwrite && existing.is_some_and(|v| !v.is_null()) {
+                return None;
+            }
+            if existing == Some(&value) {
+                return None;
+            }
+            frontmatter.insert(String::from(key), value);
+            Some(field)
+        })
+        .collect()
+}
+
+/// Words of the text, without punctuation between them, like the comma in
+/// `[cats](cats.md), dogs`.
+fn words(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// Paragraphs like `![Cover](cover.png)`, that aren't part of the summary.
+fn only_images<'a>(node: &'a AstNode<'a>) -> bool {
+    node.children().all(|c| match &c.data.borrow().value {
+        NodeValue::Image(_) | NodeValue::SoftBreak | NodeValue::LineBreak => true,
+        NodeValue::Text(t) => t.trim().is_empty(),
+        _ => false,
+    })
+}
+
+/// Alt text, which isn't counted as words.
+fn in_image<'a>(node: &'a AstNode<'a>) -> bool {
+    node.ancestors()
+        .skip(1)
+        .any(|a| matches!(a.data.borrow().value, NodeValue::Image(_)))
+}
+
+/// The text with whitespace collapsed, cut at the last word that fits in `len`
+/// characters, with an ellipsis when cut.
+fn shorten(text: &str, len: usize) -> String {
+    let mut out = String::new();
+    for word in text.split_whitespace() {
+        let sep = usize::from(!out.is_empty());
+        if out.chars().count() + sep + word.chars().count() > len {
+            out.push('…');
+            return out;
+        }
+        if sep == 1 {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use comrak::Arena;
+    use serde_yaml as yaml;
+
+    use super::{apply, Field, Options};
+    use crate::frontmatter::Frontmatter;
+    use crate::utils;
+
+    #[test]
+    fn compute_fields() {
+        let markdown = "---\n\
+            title: Kept\n\
+            ---\n\
+            \n\
+            ![Cover](cover.png)\n\
+            \n\
+            # A *new* post\n\
+            \n\
+            Some words about [cats](cats.md), [[Dogs]] and `code`,\n\
+            over two lines.\n\
+            \n\
+            ## More\n\
+            \n\
+            Another paragraph.\n";
+        let mut parse_options = utils::default_options();
+        parse_options.extension.wikilinks_title_after_pipe = true;
+        let arena = Arena::new();
+        let ast = comrak::parse_document(&arena, markdown, &parse_options);
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(19844 * 86400 + 3600);
+
+        let mut options = Options {
+            summary_length: 20,
+            words_per_minute: 5,
+            ..Default::default()
+        };
+        options.keys.links_count = String::from("links");
+        options.keys.image = String::new();
+
+        let mut frontmatter = Frontmatter::try_from(ast).unwrap();
+        let set = apply(
+            &mut frontmatter,
+            ast,
+            Some(modified),
+            &Field::ALL,
+            false,
+            &options,
+        );
+        assert_eq!(
+            set,
+            vec![
+                Field::WordCount,
+                Field::ReadingTime,
+                Field::Updated,
+                Field::Summary,
+                Field::LinksCount
+            ]
+        );
+        assert_eq!(
+            frontmatter.to_value().unwrap(),
+            yaml::from_str::<yaml::Value>(
+                "title: Kept\n\
+                word_count: 16\n\
+                reading_time: 4\n\
+                updated: 2024-05-01\n\
+                summary: Some words about…\n\
+                links: 2"
+            )
+            .unwrap()
+        );
+
+        apply(&mut frontmatter, ast, None, &[Field::Title], true, &options);
+        assert_eq!(
+            frontmatter.get(String::from("title")),
+            Some(&"A new post".into())
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use serde::ser::Error;
+use serde::{Serialize, Serializer};
+use serde_yaml as yaml;
+
+use super::FrontmatterErr;
+
+/// Language of a frontmatter, told apart by how it's delimited: `---` for
+/// YAML, `+++` for TOML, and `;;;` or a bare `{…}` object for JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Yaml,
+    Toml,
+    Json,
+}
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Yaml, Format::Toml, Format::Json];
+
+    pub fn delimiter(self) -> &'static str {
+        match self {
+            Format::Yaml => "---",
+            Format::Toml => "+++",
+            Format::Json => ";;;",
+        }
+    }
+
+    /// The format whose delimiter line starts `source`.
+    pub fn delimited(source: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| {
+            source
+                .strip_prefix(f.delimiter())
+                .is_some_and(|rest| rest.starts_with('\n') || rest.starts_with("\r\n"))
+        })
+    }
+
+    /// The format of a file by its extension, YAML for any other.
+    pub fn from_path(path: &std::path::Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Format::Toml,
+            Some("json") => Format::Json,
+            _ => Format::Yaml,
+        }
+    }
+
+    pub fn parse(self, body: &str) -> Result<yaml::Mapping, FrontmatterErr> {
+        if body.trim().is_empty() {
+            return Ok(yaml::Mapping::new());
+        }
+        match self {
+            Format::Yaml => yaml::from_str(body).map_err(FrontmatterErr::Parsing),
+            Format::Toml => {
+                let mut value: yaml::Value = toml::from_str(body).map_err(FrontmatterErr::Toml)?;
+                from_toml(&mut value);
+                match value {
+                    yaml::Value::Mapping(m) => Ok(m),
+                    _ => Err(FrontmatterErr::InvalidFrontmatter),
+                }
+            }
+            Format::Json => serde_json::from_str(body).map_err(FrontmatterErr::Json),
+        }
+    }
+
+    /// The mapping written in this format, ending with a line break.
+    pub(crate) fn serialize(self, map: &yaml::Mapping) -> Result<String, FrontmatterErr> {
+        let error = |err: &dyn std::fmt::Display| FrontmatterErr::Serializing {
+            format: self,
+            description: err.to_string(),
+        };
+        match self {
+            Format::Yaml => yaml::to_string(map).map_err(|e| error(&e)),
+            Format::Toml => {
+                toml::to_string(&Toml(&yaml::Value::Mapping(map.clone()))).map_err(|e| error(&e))
+            }
+            Format::Json => serde_json::to_string_pretty(map)
+                .map(|s| s + "\n")
+                .map_err(|e| error(&e)),
+        }
+    }
+}
+
+/// Length of the bare JSON object starting `source`, with the line break
+/// after it, like the frontmatter comrak splits off for delimiters.
+pub fn bare_json(source: &str) -> Option<usize> {
+    if !source.starts_with('{') {
+        return None;
+    }
+    let mut stream = serde_json::Deserializer::from_str(source).into_iter::<serde_json::Value>();
+    stream.next()?.ok().filter(|v| v.is_object())?;
+    let end = stream.byte_offset();
+
+    let rest = &source[end..];
+    let line = rest.find('\n').map(|i| i + 1).unwrap_or(rest.len());
+    if !rest[..line].trim().is_empty() {
+        return None;
+    }
+    let blank = match &rest[line..] {
+        r if r.starts_with('\n') => 1,
+        r if r.starts_with("\r\n") => 2,
+        _ => 0,
+    };
+    Some(end + line + blank)
+}
+
+/// TOML dates are deserialized as a mapping with a private key, so they are
+/// turned into strings.
+fn from_toml(value: &mut yaml::Value) {
+    match value {
+        yaml::Value::Mapping(m) => {
+            if m.len() == 1 {
+                if let Some(yaml::Value::String(date)) = m.get("$__toml_private_datetime") {
+                    *value = yaml::Value::String(date.clone());
+                    return;
+                }
+            }
+            m.values_mut().for_each(from_toml);
+        }
+        yaml::Value::Sequence(s) => s.iter_mut().for_each(from_toml),
+        _ => {}
+    }
+}
+
+/// Serializes a value as TOML: strings that are valid TOML dates are written
+/// as dates, and null values are an error, as TOML doesn't have them.
+struct Toml<'a>(&'a yaml::Value);
+impl Serialize for Toml<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            yaml::Value::String(s) => match s.parse::<toml::value::Datetime>() {
+                Ok(date) => date.serialize(serializer),
+                Err(_) => serializer.serialize_str(s),
+            },
+            yaml::Value::Sequence(s) => serializer.collect_seq(s.iter().map(Toml)),
+            yaml::Value::Mapping(m) => serializer.collect_map(m.iter().map(|(k, v)| (k, Toml(v)))),
+            yaml::Value::Tagged(t) => Toml(&t.value).serialize(serializer),
+            yaml::Value::Null => Err(S::Error::custom("TOML doesn't have null values")),
+            v => v.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comrak::nodes::NodeValue;
+    use comrak::Arena;
+    use serde_yaml as yaml;
+
+    use super::Format;
+    use crate::frontmatter::{Frontmatter, FrontmatterErr};
+    use crate::utils;
+
+    #[test]
+    fn frontmatter_formats() {
+        let toml = "+++\n\
+            title = \"Hello\"  # shown on the page\n\
+            date = 2024-05-01\n\
+            \n\
+            [taxonomies]\n\
+            tags = [\"art\"]\n\
+            +++\n";
+        let mut frontmatter = Frontmatter::parse(toml).unwrap();
+        assert_eq!(frontmatter.format(), Format::Toml);
+        assert_eq!(
+            frontmatter.get_path("date").unwrap(),
+            Some(&"2024-05-01".into())
+        );
+        frontmatter
+            .set_path("taxonomies.tags[1]", "ink".into())
+            .unwrap();
+        frontmatter.insert(String::from("draft"), true.into());
+        assert_eq!(
+            frontmatter.to_string(),
+            "+++\n\
+            title = \"Hello\"  # shown on the page\n\
+            date = 2024-05-01\n\
+            draft = true\n\
+            \n\
+            [taxonomies]\n\
+            tags = [\"art\", \"ink\"]\n\
+            +++\n\n"
+        );
+
+        frontmatter.convert(Format::Json).unwrap();
+        let json = format!("{}# Hello\n", frontmatter);
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, &json, &utils::default_options());
+        let first = ast.first_child().unwrap();
+        assert!(matches!(
+            first.data.borrow().value,
+            NodeValue::FrontMatter(_)
+        ));
+        assert!(matches!(
+            first.next_sibling().unwrap().data.borrow().value,
+            NodeValue::Heading(_)
+        ));
+
+        let mut frontmatter = Frontmatter::try_from(ast).unwrap();
+        assert_eq!(frontmatter.format(), Format::Json);
+        frontmatter.convert(Format::Yaml).unwrap();
+        assert_eq!(
+            frontmatter.to_string(),
+            "---\n\
+            title: Hello\n\
+            date: 2024-05-01\n\
+            taxonomies:\n  \
+              tags:\n  \
+              - art\n  \
+              - ink\n\
+            draft: true\n\
+            ---\n\n"
+        );
+        assert!(Frontmatter::parse(";;;\n{\"a\": null}\n;;;")
+            .unwrap()
+            .convert(Format::Toml)
+            .is_err());
+
+        let toml = "+++\ntitle = \"Hello\"\n+++\n\nText\n";
+        let ast = utils::parse_document(&arena, toml, &utils::default_options());
+        let mut frontmatter = Frontmatter::try_from(ast).unwrap();
+        frontmatter.set_path("draft", yaml::Value::Null).unwrap();
+        assert!(matches!(
+            frontmatter.insert_ast(ast),
+            Err(FrontmatterErr::Serializing {
+                format: Format::Toml,
+                ..
+            })
+        ));
+    }
+}
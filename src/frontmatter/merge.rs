@@ -0,0 +1,100 @@
+use serde_yaml as yaml;
+
+/// How inserted values combine with the ones already in the frontmatter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Strategy {
+    /// Inserted values replace the existing ones
+    Replace,
+    /// Mappings are merged key by key, other values are replaced
+    Deep,
+    /// Like deep, also adding the inserted items to the end of sequences
+    Append,
+    /// Like append, skipping the items already in the sequence
+    Union,
+    /// Existing values are kept, only the missing keys are added, at any
+    /// depth
+    KeepExisting,
+}
+
+/// Merges `new` into `existing`.
+pub fn merge(existing: &mut yaml::Value, new: yaml::Value, strategy: Strategy) {
+    match (existing, new) {
+        (yaml::Value::Mapping(existing), yaml::Value::Mapping(new))
+            if strategy != Strategy::Replace =>
+        {
+            for (key, value) in new {
+                match existing.get_mut(&key) {
+                    Some(e) => merge(e, value, strategy),
+                    None => {
+                        existing.insert(key, value);
+                    }
+                }
+            }
+        }
+        (yaml::Value::Sequence(existing), yaml::Value::Sequence(new))
+            if matches!(strategy, Strategy::Append | Strategy::Union) =>
+        {
+            for item in new {
+                if strategy == Strategy::Append || !existing.contains(&item) {
+                    existing.push(item);
+                }
+            }
+        }
+        (existing, new) => {
+            if strategy != Strategy::KeepExisting || existing.is_null() {
+                *existing = new;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml as yaml;
+
+    use super::{merge, Strategy};
+
+    #[test]
+    fn merge_strategies() {
+        let existing: yaml::Value =
+            yaml::from_str("title: Post\nseo: {description: A post}\ntags: [art, ink]").unwrap();
+        let new: yaml::Value =
+            yaml::from_str("title: New\nseo: {title: x}\ntags: [ink, pen]\ndraft: true").unwrap();
+        let merged = |strategy| {
+            let mut value = existing.clone();
+            merge(&mut value, new.clone(), strategy);
+            value
+        };
+        let yaml = |s| yaml::from_str::<yaml::Value>(s).unwrap();
+
+        assert_eq!(
+            merged(Strategy::Replace),
+            yaml("title: New\nseo: {title: x}\ntags: [ink, pen]\ndraft: true")
+        );
+        assert_eq!(
+            merged(Strategy::Deep),
+            yaml("title: New\nseo: {description: A post, title: x}\ntags: [ink, pen]\ndraft: true")
+        );
+        assert_eq!(
+            merged(Strategy::Append),
+            yaml(
+                "title: New\nseo: {description: A post, title: x}\n\
+                tags: [art, ink, ink, pen]\ndraft: true"
+            )
+        );
+        assert_eq!(
+            merged(Strategy::Union),
+            yaml(
+                "title: New\nseo: {description: A post, title: x}\n\
+                tags: [art, ink, pen]\ndraft: true"
+            )
+        );
+        assert_eq!(
+            merged(Strategy::KeepExisting),
+            yaml(
+                "title: Post\nseo: {description: A post, title: x}\n\
+                tags: [art, ink]\ndraft: true"
+            )
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt::Display;
+
+use serde_yaml as yaml;
+
+#[derive(Debug)]
+pub enum PathErr {
+    /// The path couldn't be parsed, at this byte
+    Syntax { path: String, position: usize },
+    /// The value at this part of the path isn't a mapping or sequence, so it
+    /// can't be indexed
+    NotAContainer(String),
+    /// A negative index before the start of a sequence, or one past its end
+    OutOfBounds(String),
+    /// The value at this path isn't a sequence, so items can't be added to or
+    /// removed from it
+    NotASequence(String),
+    /// The value at this path isn't a boolean, so it can't be toggled
+    NotABoolean(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    /// Position in a sequence, negative ones count from the end
+    Index(i64),
+}
+impl Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Segment::Key(k) if k.contains(['.', '[', ']']) => write!(f, "[{:?}]", k),
+            Segment::Key(k) => write!(f, "{}", k),
+            Segment::Index(i) => write!(f, "[{}]", i),
+        }
+    }
+}
+
+/// A path to a nested value, like `author.name`, `tags[0]` or
+/// `links[-1].url`. Keys with dots or brackets are quoted: `["og.title"]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path(pub Vec<Segment>);
+impl Display for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            if i > 0 && matches!(segment, Segment::Key(k) if !k.contains(['.', '[', ']'])) {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", segment)?;
+        }
+        Ok(())
+    }
+}
+
+impl Path {
+    pub fn parse(path: &str) -> Result<Self, PathErr> {
+        let error = |position| PathErr::Syntax {
+            path: String::from(path),
+            position,
+        };
+        let mut segments = vec![];
+        let mut rest = path;
+        while !rest.is_empty() {
+            let position = path.len() - rest.len();
+            if let Some(inner) = rest.strip_prefix('[') {
+                let end = match inner.strip_prefix('"') {
+                    Some(quoted) => quoted.find('"').map(|i| i + 2),
+                    None => inner.find(']'),
+                }
+                .ok_or(error(position))?;
+                let (content, after) = inner.split_at(end);
+                rest = after.strip_prefix(']').ok_or(error(position + end + 1))?;
+                segments.push(match content.strip_prefix('"') {
+                    Some(key) => Segment::Key(String::from(&key[..key.len() - 1])),
+                    None => Segment::Index(content.trim().parse().map_err(|_| error(position))?),
+                });
+            } else {
+                let rest_of = rest.strip_prefix('.').filter(|_| !segments.is_empty());
+                let key = rest_of.unwrap_or(rest);
+                let end = key.find(['.', '[']).unwrap_or(key.len());
+                if end == 0 {
+                    return Err(error(position));
+                }
+                segments.push(Segment::Key(String::from(&key[..end])));
+                rest = &key[end..];
+            }
+        }
+        if segments.is_empty() {
+            return Err(error(0));
+        }
+        Ok(Self(segments))
+    }
+
+    /// The path of its first `len` segments, for errors.
+    fn prefix(&self, len: usize) -> String {
+        Path(self.0[..len].to_vec()).to_string()
+    }
+}
+
+fn index(len: usize, i: i64) -> Option<usize> {
+    if i < 0 {
+        len.checked_sub(i.unsigned_abs() as usize)
+    } else {
+        Some(i as usize)
+    }
+}
+
+pub fn get<'v>(value: &'v yaml::Value, path: &[Segment]) -> Option<&'v yaml::Value> {
+    path.iter()
+        .try_fold(value, |value, segment| match (segment, value) {
+            (Segment::Key(k), yaml::Value::Mapping(m)) => m.get(k.as_str()),
+            (Segment::Index(i), yaml::Value::Sequence(s)) => s.get(index(s.len(), *i)?),
+            _ => None,
+        })
+}
+
+pub fn get_mut<'v>(value: &'v mut yaml::Value, path: &[Segment]) -> Option<&'v mut yaml::Value> {
+    path.iter()
+        .try_fold(value, |value, segment| match (segment, value) {
+            (Segment::Key(k), yaml::Value::Mapping(m)) => m.get_mut(k.as_str()),
+            (Segment::Index(i), yaml::Value::Sequence(s)) => {
+                let i = index(s.len(), *i)?;
+                s.get_mut(i)
+            }
+            _ => None,
+        })
+}
+
+/// Sets the value at `path`, creating the mappings and sequences before it.
+/// An index equal to a sequence's length appends to it.
+pub fn set(root: &mut yaml::Value, path: &Path, value: yaml::Value) -> Result<(), PathErr> {
+    let mut current = root;
+    for (n, segment) in path.0.iter().enumerate() {
+        if current.is_null() {
+            *current = match segment {
+                Segment::Key(_) => yaml::Value::Mapping(yaml::Mapping::new()),
+                Segment::Index(_) => yaml::Value::Sequence(vec![]),
+            };
+        }
+        current = match (segment, current) {
+            (Segment::Key(k), yaml::Value::Mapping(m)) => m
+                .entry(yaml::Value::String(k.clone()))
+                .or_insert(yaml::Value::Null),
+            (Segment::Index(i), yaml::Value::Sequence(s)) => {
+                let i = index(s.len(), *i)
+                    .filter(|i| *i <= s.len())
+                    .ok_or(PathErr::OutOfBounds(path.prefix(n + 1)))?;
+                if i == s.len() {
+                    s.push(yaml::Value::Null);
+                }
+                &mut s[i]
+            }
+            _ => return Err(PathErr::NotAContainer(path.prefix(n))),
+        };
+    }
+    *current = value;
+    Ok(())
+}
+
+/// Removes the value at `path`, returning it if it existed.
+pub fn remove(root: &mut yaml::Value, path: &[Segment]) -> Option<yaml::Value> {
+    let (last, parents) = path.split_last()?;
+    match (last, get_mut(root, parents)?) {
+        (Segment::Key(k), yaml::Value::Mapping(m)) => m.remove(k.as_str()),
+        (Segment::Index(i), yaml::Value::Sequence(s)) => {
+            let i = index(s.len(), *i).filter(|i| *i < s.len())?;
+            Some(s.remove(i))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml as yaml;
+
+    use super::{get, remove, set, Path, PathErr, Segment};
+
+    #[test]
+    fn nested_paths() {
+        let path = Path::parse("links[-1].url").unwrap();
+        assert_eq!(
+            path.0,
+            vec![
+                Segment::Key("links".into()),
+                Segment::Index(-1),
+                Segment::Key("url".into())
+            ]
+        );
+        assert_eq!(
+            Path::parse("seo[\"og.title\"]").unwrap().0,
+            vec![Segment::Key("seo".into()), Segment::Key("og.title".into())]
+        );
+        assert_eq!(path.to_string(), "links[-1].url");
+        assert!(Path::parse("a..b").is_err());
+        assert!(Path::parse("a[x]").is_err());
+
+        let mut value: yaml::Value =
+            yaml::from_str("author: {name: Guz}\nlinks: [{url: a}, {url: b}]").unwrap();
+        assert_eq!(get(&value, &path.0), Some(&yaml::Value::String("b".into())));
+
+        set(
+            &mut value,
+            &Path::parse("tumblr.tags[0]").unwrap(),
+            "art".into(),
+        )
+        .unwrap();
+        assert!(matches!(
+            set(
+                &mut value,
+                &Path::parse("tumblr.tags[1000000000]").unwrap(),
+                "ink".into()
+            ),
+            Err(PathErr::OutOfBounds(p)) if p == "tumblr.tags[1000000000]"
+        ));
+        set(
+            &mut value,
+            &Path::parse("author.name").unwrap(),
+            "Gustavo".into(),
+        )
+        .unwrap();
+        assert!(set(
+            &mut value,
+            &Path::parse("author.name.first").unwrap(),
+            "G".into()
+        )
+        .is_err());
+        assert_eq!(
+            remove(&mut value, &Path::parse("links[0]").unwrap().0),
+            yaml::from_str("{url: a}").ok()
+        );
+        assert_eq!(
+            remove(&mut value, &Path::parse("links[3]").unwrap().0),
+            None
+        );
+
+        assert_eq!(
+            value,
+            yaml::from_str::<yaml::Value>(
+                "author: {name: Gustavo}\nlinks: [{url: b}]\ntumblr: {tags: [art]}"
+            )
+            .unwrap()
+        );
+    }
+}
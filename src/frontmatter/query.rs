@@ -0,0 +1,501 @@
+use std::cmp::Ordering;
+
+use serde::ser::SerializeMap;
+use serde::Serialize;
+use serde_yaml as yaml;
+
+use super::path::{self, Path, PathErr};
+use crate::utils;
+
+#[derive(Debug)]
+pub enum QueryErr {
+    /// The query couldn't be parsed, at this byte
+    Syntax {
+        query: String,
+        position: usize,
+        message: &'static str,
+    },
+    Path(PathErr),
+    Regex(regex::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// An item of a list, a substring of a string, or a key of a mapping
+    Contains,
+}
+
+#[derive(Debug, Clone)]
+pub enum Operand {
+    Path(Path),
+    Value(yaml::Value),
+}
+
+/// A filter on frontmatter values, like
+/// `state == "draft" && tags contains "art" && date < 2024-05-01`.
+///
+/// Operands are paths to values, like `author.name`, quoted strings, numbers,
+/// `true`, `false`, `null`, and dates like `2024-05-01`, or `today`,
+/// `today+7` and `today-7` for the current date in UTC and days from it.
+/// They are compared with `==`, `!=`, `<`, `<=`, `>`, `>=`, `contains` and
+/// `matches`, taking a regex, and combined with `&&`, `||`, `!` and
+/// parentheses. An operand alone is true when the value isn't missing, null,
+/// false or empty. Dates are compared as strings, so they must be written
+/// the same way.
+#[derive(Debug, Clone)]
+pub enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Compare(Operand, Op, Operand),
+    Matches(Operand, regex::Regex),
+    Truthy(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Op(Op),
+    Matches,
+    Operand(String),
+    String(String),
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self, QueryErr> {
+        let tokens = tokenize(query)?;
+        let mut parser = Parser {
+            query,
+            tokens: &tokens,
+            pos: 0,
+        };
+        let parsed = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            Some((position, _)) => Err(parser.error(*position, "unexpected token")),
+            None => Ok(parsed),
+        }
+    }
+
+    /// Whether the frontmatter, as a YAML mapping, matches the query.
+    pub fn matches(&self, root: &yaml::Value) -> bool {
+        match self {
+            Query::And(a, b) => a.matches(root) && b.matches(root),
+            Query::Or(a, b) => a.matches(root) || b.matches(root),
+            Query::Not(q) => !q.matches(root),
+            Query::Compare(a, op, b) => {
+                let (a, b) = (a.resolve(root), b.resolve(root));
+                match op {
+                    Op::Eq => equals(a, b),
+                    Op::Ne => !equals(a, b),
+                    Op::Lt => compare(a, b) == Some(Ordering::Less),
+                    Op::Le => compare(a, b).is_some_and(|o| o != Ordering::Greater),
+                    Op::Gt => compare(a, b) == Some(Ordering::Greater),
+                    Op::Ge => compare(a, b).is_some_and(|o| o != Ordering::Less),
+                    Op::Contains => match (a, b) {
+                        (yaml::Value::Sequence(s), b) => s.iter().any(|v| equals(v, b)),
+                        (yaml::Value::String(s), yaml::Value::String(b)) => s.contains(b.as_str()),
+                        (yaml::Value::Mapping(m), b) => m.contains_key(b),
+                        _ => false,
+                    },
+                }
+            }
+            Query::Matches(a, regex) => a.resolve(root).as_str().is_some_and(|s| regex.is_match(s)),
+            Query::Truthy(a) => match a.resolve(root) {
+                yaml::Value::Null | yaml::Value::Bool(false) => false,
+                yaml::Value::String(s) => !s.is_empty(),
+                yaml::Value::Sequence(s) => !s.is_empty(),
+                yaml::Value::Mapping(m) => !m.is_empty(),
+                _ => true,
+            },
+        }
+    }
+}
+
+impl Operand {
+    fn resolve<'v>(&'v self, root: &'v yaml::Value) -> &'v yaml::Value {
+        match self {
+            Operand::Path(p) => path::get(root, &p.0).unwrap_or(&yaml::Value::Null),
+            Operand::Value(v) => v,
+        }
+    }
+}
+
+fn equals(a: &yaml::Value, b: &yaml::Value) -> bool {
+    compare(a, b) == Some(Ordering::Equal) || a == b
+}
+
+fn compare(a: &yaml::Value, b: &yaml::Value) -> Option<Ordering> {
+    match (a, b) {
+        (yaml::Value::Number(a), yaml::Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (yaml::Value::String(a), yaml::Value::String(b)) => Some(a.cmp(b)),
+        (yaml::Value::Bool(a), yaml::Value::Bool(b)) => Some(a.cmp(b)),
+        (yaml::Value::Null, yaml::Value::Null) => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<(usize, Token)>, QueryErr> {
+    let error = |position, message| QueryErr::Syntax {
+        query: String::from(query),
+        position,
+        message,
+    };
+    let mut tokens = vec![];
+    let mut chars = query.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let token = match (c, next) {
+            (c, _) if c.is_whitespace() => continue,
+            ('(', _) => Token::Open,
+            (')', _) => Token::Close,
+            ('&', Some('&')) => Token::And,
+            ('|', Some('|')) => Token::Or,
+            ('=', Some('=')) => Token::Op(Op::Eq),
+            ('!', Some('=')) => Token::Op(Op::Ne),
+            ('<', Some('=')) => Token::Op(Op::Le),
+            ('>', Some('=')) => Token::Op(Op::Ge),
+            ('!', _) => Token::Not,
+            ('<', _) => Token::Op(Op::Lt),
+            ('>', _) => Token::Op(Op::Gt),
+            ('&' | '|' | '=', _) => return Err(error(i, "expected &&, || or ==")),
+            ('"', _) => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => string.push(c),
+                            None => return Err(error(i, "unterminated string")),
+                        },
+                        Some((_, c)) => string.push(c),
+                        None => return Err(error(i, "unterminated string")),
+                    }
+                }
+                Token::String(string)
+            }
+            _ => {
+                let mut word = String::from(c);
+                let mut brackets = usize::from(c == '[');
+                while let Some((_, c)) = chars.peek() {
+                    if brackets == 0 && (c.is_whitespace() || "()!=<>&|\"".contains(*c)) {
+                        break;
+                    }
+                    match c {
+                        '[' => brackets += 1,
+                        ']' => brackets = brackets.saturating_sub(1),
+                        _ => {}
+                    }
+                    word.push(*c);
+                    chars.next();
+                }
+                tokens.push((
+                    i,
+                    match word.as_str() {
+                        "contains" => Token::Op(Op::Contains),
+                        "matches" => Token::Matches,
+                        _ => Token::Operand(word),
+                    },
+                ));
+                continue;
+            }
+        };
+        if matches!(
+            token,
+            Token::And | Token::Or | Token::Op(Op::Eq | Op::Ne | Op::Le | Op::Ge)
+        ) {
+            chars.next();
+        }
+        tokens.push((i, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'q> {
+    query: &'q str,
+    tokens: &'q [(usize, Token)],
+    pos: usize,
+}
+impl Parser<'_> {
+    fn error(&self, position: usize, message: &'static str) -> QueryErr {
+        QueryErr::Syntax {
+            query: String::from(self.query),
+            position,
+            message,
+        }
+    }
+
+    fn next(&mut self) -> Option<&(usize, Token)> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn or(&mut self) -> Result<Query, QueryErr> {
+        let mut query = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query, QueryErr> {
+        let mut query = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            query = Query::And(Box::new(query), Box::new(self.not()?));
+        }
+        Ok(query)
+    }
+
+    fn not(&mut self) -> Result<Query, QueryErr> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Query::Not(Box::new(self.not()?)))
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let query = self.or()?;
+                match self.next().cloned() {
+                    Some((_, Token::Close)) => Ok(query),
+                    Some((position, _)) => Err(self.error(position, "expected \")\"")),
+                    None => Err(self.error(self.query.len(), "expected \")\"")),
+                }
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Query, QueryErr> {
+        let left = self.operand()?;
+        match self.peek().cloned() {
+            Some(Token::Op(op)) => {
+                self.pos += 1;
+                Ok(Query::Compare(left, op, self.operand()?))
+            }
+            Some(Token::Matches) => {
+                self.pos += 1;
+                let position = self.tokens.get(self.pos).map(|(p, _)| *p);
+                match self.operand()? {
+                    Operand::Value(yaml::Value::String(r)) => Ok(Query::Matches(
+                        left,
+                        regex::Regex::new(&r).map_err(QueryErr::Regex)?,
+                    )),
+                    _ => Err(self.error(position.unwrap_or(0), "expected a quoted regex")),
+                }
+            }
+            _ => Ok(Query::Truthy(left)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, QueryErr> {
+        let (position, token) = match self.next() {
+            Some((p, t)) => (*p, t.clone()),
+            None => return Err(self.error(self.query.len(), "expected a value")),
+        };
+        let word = match token {
+            Token::String(s) => return Ok(Operand::Value(yaml::Value::String(s))),
+            Token::Operand(word) => word,
+            _ => return Err(self.error(position, "expected a value")),
+        };
+        Ok(Operand::Value(match word.as_str() {
+            "true" => yaml::Value::Bool(true),
+            "false" => yaml::Value::Bool(false),
+            "null" => yaml::Value::Null,
+            w if w.starts_with("today") => {
+                let offset = match &w[5..] {
+                    "" => 0,
+                    o if o.starts_with(['+', '-']) => o
+                        .trim_start_matches('+')
+                        .parse()
+                        .map_err(|_| self.error(position, "expected a number of days"))?,
+                    _ => return Ok(Operand::Path(Path::parse(w).map_err(QueryErr::Path)?)),
+                };
+                let date = today(offset).ok_or_else(|| {
+                    self.error(position, "the date is before year 0 or after 9999")
+                })?;
+                yaml::Value::String(date)
+            }
+            w if w.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                match w.parse::<i64>() {
+                    Ok(n) => yaml::Value::Number(n.into()),
+                    Err(_) => match w.parse::<f64>() {
+                        Ok(n) => yaml::Value::Number(n.into()),
+                        // Dates and times, like 2024-05-01
+                        Err(_) => yaml::Value::String(String::from(w)),
+                    },
+                }
+            }
+            w => return Ok(Operand::Path(Path::parse(w).map_err(QueryErr::Path)?)),
+        }))
+    }
+}
+
+/// The current date in UTC, moved by `offset` days, like `2024-05-01`, or
+/// `None` when it doesn't have four digit years.
+fn today(offset: i64) -> Option<String> {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let days = secs.div_euclid(86400).checked_add(offset)?;
+    // From 0000-01-01 to 9999-12-31
+    (-719_528..=2_932_896)
+        .contains(&days)
+        .then(|| utils::format_date(days))
+}
+
+/// How the documents matching a query are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    /// Each document in a line, with its fields separated by tabs
+    Lines,
+    /// A header with the fields, and a row for each document
+    Csv,
+    /// An array of objects with the path and fields of each document
+    Json,
+}
+
+/// A document matching a query, with the values of the selected fields.
+#[derive(Debug, Clone, Serialize)]
+pub struct Match {
+    pub path: String,
+    pub values: Vec<yaml::Value>,
+}
+impl Match {
+    pub fn new(path: String, root: &yaml::Value, fields: &[Path]) -> Self {
+        Self {
+            path,
+            values: fields
+                .iter()
+                .map(|f| path::get(root, &f.0).cloned().unwrap_or(yaml::Value::Null))
+                .collect(),
+        }
+    }
+}
+
+/// Writes the matches with the values of `fields`, the paths they were
+/// selected with.
+pub fn write(
+    matches: &[Match],
+    fields: &[Path],
+    output: Output,
+) -> Result<String, serde_json::Error> {
+    let text = |v: &yaml::Value| match v {
+        yaml::Value::String(s) => Ok(s.clone()),
+        yaml::Value::Null => Ok(String::new()),
+        v => serde_json::to_string(v),
+    };
+    match output {
+        Output::Lines => matches
+            .iter()
+            .map(|m| {
+                let values = m.values.iter().map(text).collect::<Result<Vec<_>, _>>()?;
+                Ok(std::iter::once(m.path.clone())
+                    .chain(values)
+                    .collect::<Vec<_>>()
+                    .join("\t")
+                    + "\n")
+            })
+            .collect(),
+        Output::Csv => {
+            let row = |cells: Vec<String>| {
+                cells
+                    .iter()
+                    .map(|c| {
+                        if c.contains([',', '"', '\n', '\r']) {
+                            format!("\"{}\"", c.replace('"', "\"\""))
+                        } else {
+                            c.clone()
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+                    + "\n"
+            };
+            let header = std::iter::once(String::from("path"))
+                .chain(fields.iter().map(|f| f.to_string()))
+                .collect();
+            let mut out = row(header);
+            for m in matches {
+                let values = m.values.iter().map(text).collect::<Result<Vec<_>, _>>()?;
+                out.push_str(&row(std::iter::once(m.path.clone())
+                    .chain(values)
+                    .collect()));
+            }
+            Ok(out)
+        }
+        Output::Json => {
+            let rows = matches.iter().map(|m| Row(m, fields)).collect::<Vec<_>>();
+            Ok(serde_json::to_string_pretty(&rows)? + "\n")
+        }
+    }
+}
+
+/// A match as a JSON object, with its path and fields in order.
+struct Row<'a>(&'a Match, &'a [Path]);
+impl Serialize for Row<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.1.len() + 1))?;
+        map.serialize_entry("path", &self.0.path)?;
+        for (field, value) in self.1.iter().zip(&self.0.values) {
+            map.serialize_entry(&field.to_string(), value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml as yaml;
+
+    use super::{Query, QueryErr};
+    use crate::utils;
+
+    #[test]
+    fn query_frontmatter() {
+        let post: yaml::Value = yaml::from_str(
+            "state: draft\ntags: [art, ink]\ndate: 2024-04-20\nviews: 30\nauthor: {name: Guz}",
+        )
+        .unwrap();
+        let matches = |q| Query::parse(q).unwrap().matches(&post);
+
+        assert!(matches(
+            "state == \"draft\" && tags contains \"art\" && date < 2024-05-01"
+        ));
+        assert!(matches("views >= 30 && !(views > 30) && author.name"));
+        assert!(matches("tags[1] == \"ink\" || missing"));
+        assert!(matches(
+            "author.name matches \"^G\" && author contains \"name\""
+        ));
+        assert!(!matches("state != \"draft\" || date >= 2024-05-01"));
+        assert!(!matches("missing || tags contains \"pen\""));
+        assert!(matches("date < today"));
+
+        assert!(Query::parse("state == ").is_err());
+        assert!(Query::parse("(state == \"draft\"").is_err());
+        assert!(Query::parse("state \"draft\"").is_err());
+        assert!(matches!(
+            Query::parse("date < today+9223372036854775807"),
+            Err(QueryErr::Syntax { position: 7, .. })
+        ));
+        assert!(Query::parse("date > today-99999999999999999999").is_err());
+        assert_eq!(utils::format_date(19844), "2024-05-01");
+        assert_eq!(utils::format_date(-719_528), "0000-01-01");
+        assert_eq!(utils::format_date(2_932_896), "9999-12-31");
+    }
+}
@@ -0,0 +1,311 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_yaml as yaml;
+
+use super::path::{self, Path, PathErr, Segment};
+use super::{Frontmatter, FrontmatterErr};
+
+#[derive(Debug)]
+pub enum SchemaErr {
+    Io {
+        path: PathBuf,
+        err: std::io::Error,
+    },
+    Parsing(yaml::Error),
+    /// A key of the schema isn't a valid path
+    Path(PathErr),
+    Regex(regex::Error),
+}
+
+/// Type of a frontmatter value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Type {
+    String,
+    /// A string with a date, and optionally a time, like `2024-05-01`
+    Date,
+    /// A list of strings
+    List,
+    /// A string out of the field's `values`
+    Enum,
+    Number,
+    Boolean,
+}
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::String => write!(f, "a string"),
+            Type::Date => write!(f, "a date"),
+            Type::List => write!(f, "a list of strings"),
+            Type::Enum => write!(f, "one of the allowed values"),
+            Type::Number => write!(f, "a number"),
+            Type::Boolean => write!(f, "true or false"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldDef {
+    #[serde(rename = "type")]
+    kind: Option<Type>,
+    #[serde(default)]
+    required: bool,
+    pattern: Option<String>,
+    #[serde(default)]
+    values: Vec<String>,
+    default: Option<yaml::Value>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub path: Path,
+    pub kind: Option<Type>,
+    pub required: bool,
+    /// Regex the string values, or each string of a list, must match
+    pub pattern: Option<regex::Regex>,
+    /// Values allowed for enums
+    pub values: Vec<String>,
+    /// Value of the field when it's missing, see [`Schema::fill_defaults`]
+    pub default: Option<yaml::Value>,
+}
+
+/// What a frontmatter must look like, loaded from a YAML file mapping keys,
+/// or paths to nested values, to their fields:
+///
+/// ```yaml
+/// title: { type: string, required: true }
+/// date: { type: date, required: true }
+/// tags: { type: list, pattern: "^[a-z-]+$", default: [] }
+/// state: { type: enum, values: [draft, published], default: draft }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Schema {
+    pub fields: Vec<Field>,
+}
+impl Schema {
+    pub fn load(path: &std::path::Path) -> Result<Self, SchemaErr> {
+        let file = std::fs::read_to_string(path).map_err(|err| SchemaErr::Io {
+            path: path.to_path_buf(),
+            err,
+        })?;
+        Self::parse(&file)
+    }
+
+    pub fn parse(string: &str) -> Result<Self, SchemaErr> {
+        let fields: yaml::Mapping = yaml::from_str(string).map_err(SchemaErr::Parsing)?;
+        let fields = fields
+            .into_iter()
+            .map(|(key, def)| {
+                let key: String = yaml::from_value(key).map_err(SchemaErr::Parsing)?;
+                let def: FieldDef = yaml::from_value(def).map_err(SchemaErr::Parsing)?;
+                Ok(Field {
+                    path: Path::parse(&key).map_err(SchemaErr::Path)?,
+                    kind: def.kind,
+                    required: def.required,
+                    pattern: def
+                        .pattern
+                        .map(|p| regex::Regex::new(&p))
+                        .transpose()
+                        .map_err(SchemaErr::Regex)?,
+                    values: def.values,
+                    default: def.default,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { fields })
+    }
+
+    /// The fields the frontmatter doesn't follow, in the order of the schema.
+    /// Null values count as missing.
+    pub fn validate(&self, frontmatter: &Frontmatter) -> Vec<Violation> {
+        let root = yaml::Value::Mapping(frontmatter.map.clone());
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let problem = match path::get(&root, &field.path.0) {
+                    None | Some(yaml::Value::Null) => field.required.then_some(Problem::Missing),
+                    Some(value) => field.check(value),
+                }?;
+                let line = match field.path.0.first() {
+                    Some(Segment::Key(key)) => frontmatter.line(key),
+                    _ => None,
+                };
+                Some(Violation {
+                    key: field.path.to_string(),
+                    line,
+                    problem,
+                })
+            })
+            .collect()
+    }
+
+    /// Sets the missing fields that have a default.
+    pub fn fill_defaults(&self, frontmatter: &mut Frontmatter) -> Result<(), FrontmatterErr> {
+        for field in &self.fields {
+            let Some(default) = &field.default else {
+                continue;
+            };
+            if frontmatter
+                .get_path(&field.path.to_string())?
+                .is_none_or(|v| v.is_null())
+            {
+                frontmatter.set_path(&field.path.to_string(), default.clone())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Field {
+    fn check(&self, value: &yaml::Value) -> Option<Problem> {
+        let kind = match self.kind {
+            None if !self.values.is_empty() => Some(Type::Enum),
+            kind => kind,
+        };
+        let valid = match kind {
+            None => true,
+            Some(Type::String | Type::Enum) => value.is_string(),
+            Some(Type::Date) => value
+                .as_str()
+                .and_then(|s| s.parse::<toml::value::Datetime>().ok())
+                .is_some_and(|d| d.date.is_some()),
+            Some(Type::List) => value
+                .as_sequence()
+                .is_some_and(|s| s.iter().all(|v| v.is_string())),
+            Some(Type::Number) => value.is_number(),
+            Some(Type::Boolean) => value.is_bool(),
+        };
+        if let Some(kind) = kind.filter(|_| !valid) {
+            return Some(Problem::WrongType(kind));
+        }
+
+        let strings = match value {
+            yaml::Value::String(s) => vec![s.as_str()],
+            yaml::Value::Sequence(s) => s.iter().filter_map(|v| v.as_str()).collect(),
+            _ => vec![],
+        };
+        if kind == Some(Type::Enum) && !strings.iter().all(|s| self.values.iter().any(|v| v == s)) {
+            return Some(Problem::NotAllowed(self.values.clone()));
+        }
+        match &self.pattern {
+            Some(p) if !strings.iter().all(|s| p.is_match(s)) => {
+                Some(Problem::Pattern(p.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "problem", content = "expected")]
+pub enum Problem {
+    /// A required key is missing or null
+    Missing,
+    WrongType(Type),
+    /// The value isn't one of the enum's values
+    NotAllowed(Vec<String>),
+    /// A string doesn't match the field's pattern
+    Pattern(String),
+}
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing required key"),
+            Problem::WrongType(t) => write!(f, "expected {}", t),
+            Problem::NotAllowed(values) => write!(f, "expected one of {}", values.join(", ")),
+            Problem::Pattern(p) => write!(f, "doesn't match {}", p),
+        }
+    }
+}
+
+/// A field the frontmatter doesn't follow, and the line of its key inside the
+/// frontmatter block, when the key is there.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub key: String,
+    pub line: Option<usize>,
+    #[serde(flatten)]
+    pub problem: Problem,
+}
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}: {}: {}", line, self.key, self.problem),
+            None => write!(f, "{}: {}", self.key, self.problem),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Problem, Schema, Type};
+    use crate::frontmatter::Frontmatter;
+
+    #[test]
+    fn validate_schema() {
+        let schema = Schema::parse(
+            "title: { type: string, required: true }\n\
+            date: { type: date, required: true }\n\
+            tags: { type: list, pattern: '^[a-z-]+$', default: [] }\n\
+            state: { values: [draft, published], default: draft }\n\
+            author.name: { type: string, default: Guz }\n",
+        )
+        .unwrap();
+
+        let mut frontmatter = Frontmatter::parse(
+            "---\n\
+            # Post\n\
+            date: 2024-05-1\n\
+            tags: art\n\
+            state: drafted\n\
+            ---\n",
+        )
+        .unwrap();
+        let violations = schema
+            .validate(&frontmatter)
+            .into_iter()
+            .map(|v| (v.key, v.line, v.problem))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            violations,
+            vec![
+                (String::from("title"), None, Problem::Missing),
+                (
+                    String::from("date"),
+                    Some(3),
+                    Problem::WrongType(Type::Date)
+                ),
+                (
+                    String::from("tags"),
+                    Some(4),
+                    Problem::WrongType(Type::List)
+                ),
+                (
+                    String::from("state"),
+                    Some(5),
+                    Problem::NotAllowed(vec![String::from("draft"), String::from("published")])
+                ),
+            ]
+        );
+
+        frontmatter.remove(String::from("tags"));
+        frontmatter.insert(String::from("title"), "Hello".into());
+        frontmatter.insert(String::from("date"), "2024-05-01".into());
+        frontmatter.insert(String::from("state"), "draft".into());
+        schema.fill_defaults(&mut frontmatter).unwrap();
+        assert_eq!(schema.validate(&frontmatter), vec![]);
+        assert_eq!(
+            frontmatter.get_path("author.name").unwrap(),
+            Some(&"Guz".into())
+        );
+
+        frontmatter.set_path("tags", vec!["Art"].into()).unwrap();
+        assert_eq!(
+            schema.validate(&frontmatter)[0].problem,
+            Problem::Pattern(String::from("^[a-z-]+$"))
+        );
+    }
+}
@@ -1,4 +1,8 @@
+pub mod config;
 pub mod convert;
+pub mod document;
+pub mod files;
 pub mod frontmatter;
 pub mod links;
+pub mod splice;
 pub mod utils;
@@ -1,10 +1,83 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
 
 use comrak::arena_tree::Node;
-use comrak::nodes::{Ast, NodeLink, NodeValue};
+use comrak::nodes::{Ast, AstNode, LineColumn, NodeLink, NodeValue, NodeWikiLink};
+use comrak::Arena;
+use serde::Serialize;
 
+use crate::splice::{self, Lines, Span, SpanKind};
 use crate::utils;
 
+pub mod audit;
+pub mod check;
+pub mod normalize;
+pub mod rebase;
+pub mod references;
+pub mod rewrite;
+
+use rewrite::Rules;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    /// `[text](url)`
+    Inline,
+    /// `[text][label]`, with the URL in a link reference definition
+    Reference,
+    /// `<url>`, or a bare URL with the autolink extension
+    Autolink,
+    /// `[[Page]]`
+    Wikilink,
+}
+
+/// A link and where it is in the document. Lines and columns start at 1, and
+/// columns count bytes.
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LinkInfo {
+    pub url: String,
+    pub text: String,
+    pub title: Option<String>,
+    pub kind: LinkKind,
+    pub line: usize,
+    pub column: usize,
+    /// Text of the heading of the section the link is in
+    pub heading: Option<String>,
+}
+impl Display for LinkInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.url)
+    }
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageInfo {
+    pub url: String,
+    pub alt: String,
+    pub title: Option<String>,
+    pub kind: LinkKind,
+    pub line: usize,
+    pub column: usize,
+    pub heading: Option<String>,
+}
+impl Display for ImageInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.url)
+    }
+}
+
+/// What happens to the text of removed links, or the alt text of images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Removal {
+    /// Keep the text, removing only the link
+    Unlink,
+    /// Remove the link with its text
+    Delete,
+}
+
 pub fn iterate_links<'a, F>(ast: &'a Node<'a, RefCell<Ast>>, iterator: F)
 where
     F: Fn(&mut NodeLink),
@@ -16,7 +89,7 @@ where
     });
 }
 
-pub fn replace_links<'a>(ast: &'a Node<'a, RefCell<Ast>>, from: &'a str, to: &'a str) {
+pub fn replace_links<'a>(ast: &'a Node<'a, RefCell<Ast>>, from: &str, to: &str) {
     iterate_links(ast, |l| {
         if l.url == from {
             l.url = String::from(to)
@@ -24,14 +97,65 @@ pub fn replace_links<'a>(ast: &'a Node<'a, RefCell<Ast>>, from: &'a str, to: &'a
     });
 }
 
-pub fn remove_link<'a>(ast: &'a Node<'a, RefCell<Ast>>, url: &'a str) {
-    utils::iter_nodes(ast, &|node| {
-        if let NodeValue::Link(ref mut l) = &mut node.data.borrow_mut().value {
-            if l.url == url {
-                node.children().for_each(|n| node.insert_before(n));
-                node.detach();
-            }
+/// Rewrites the links matching any of the rules, in one pass.
+pub fn rewrite_links<'a>(ast: &'a Node<'a, RefCell<Ast>>, rules: &Rules) {
+    iterate_links(ast, |l| {
+        if let Some(url) = rules.rewrite(&l.url) {
+            l.url = url
         }
+    });
+}
+
+/// Canonicalizes the links' URLs, see [`normalize::Options`].
+pub fn normalize_links<'a>(ast: &'a Node<'a, RefCell<Ast>>, options: &normalize::Options) {
+    iterate_links(ast, |l| l.url = options.normalize(&l.url));
+}
+
+pub fn normalize_images<'a>(ast: &'a Node<'a, RefCell<Ast>>, options: &normalize::Options) {
+    iterate_images(ast, |l| l.url = options.normalize(&l.url));
+}
+
+/// The links and images whose URLs can't be normalized.
+pub fn normalize_failures<'a>(
+    ast: &'a Node<'a, RefCell<Ast>>,
+    options: &normalize::Options,
+) -> Vec<normalize::Failure> {
+    let links = get_links(ast).into_iter().map(|url| (url, false));
+    let images = get_images(ast).into_iter().map(|url| (url, true));
+    links
+        .chain(images)
+        .filter_map(|(url, image)| {
+            Some(normalize::Failure {
+                problem: options.problem(&url)?,
+                url,
+                image,
+            })
+        })
+        .collect()
+}
+
+/// Rewrites the relative links so they still resolve after a move, see
+/// [`rebase::Rebase`].
+pub fn rebase_links<'a>(ast: &'a Node<'a, RefCell<Ast>>, rebase: &rebase::Rebase) {
+    iterate_links(ast, |l| {
+        if let Some(url) = rebase.rebase(&l.url) {
+            l.url = url
+        }
+    });
+}
+
+pub fn remove_link<'a>(ast: &'a Node<'a, RefCell<Ast>>, url: &str) {
+    remove_links_where(ast, Removal::Unlink, |u| u == url)
+}
+
+/// Removes the links whose URL `f` accepts.
+pub fn remove_links_where<'a, F>(ast: &'a Node<'a, RefCell<Ast>>, removal: Removal, f: F)
+where
+    F: Fn(&str) -> bool,
+{
+    remove_nodes(ast, removal, |v| match v {
+        NodeValue::Link(l) => f(&l.url),
+        _ => false,
     })
 }
 
@@ -42,6 +166,56 @@ pub fn get_links<'a>(ast: &'a Node<'a, RefCell<Ast>>) -> Vec<String> {
     r
 }
 
+pub fn iterate_wikilinks<'a, F>(ast: &'a Node<'a, RefCell<Ast>>, iterator: F)
+where
+    F: Fn(&mut NodeWikiLink),
+{
+    utils::iter_nodes(ast, &|node| {
+        if let NodeValue::WikiLink(ref mut l) = &mut node.data.borrow_mut().value {
+            iterator(l);
+        };
+    });
+}
+
+pub fn replace_wikilinks<'a>(ast: &'a Node<'a, RefCell<Ast>>, from: &str, to: &str) {
+    iterate_wikilinks(ast, |l| {
+        if l.url == from {
+            l.url = String::from(to)
+        }
+    });
+}
+
+pub fn rewrite_wikilinks<'a>(ast: &'a Node<'a, RefCell<Ast>>, rules: &Rules) {
+    iterate_wikilinks(ast, |l| {
+        if let Some(url) = rules.rewrite(&l.url) {
+            l.url = url
+        }
+    });
+}
+
+pub fn remove_wikilink<'a>(ast: &'a Node<'a, RefCell<Ast>>, url: &str) {
+    remove_wikilinks_where(ast, Removal::Unlink, |u| u == url)
+}
+
+pub fn remove_wikilinks_where<'a, F>(ast: &'a Node<'a, RefCell<Ast>>, removal: Removal, f: F)
+where
+    F: Fn(&str) -> bool,
+{
+    remove_nodes(ast, removal, |v| match v {
+        NodeValue::WikiLink(l) => f(&l.url),
+        _ => false,
+    })
+}
+
+/// Targets of the `[[Page]]` and `[[Page|alias]]` links, which
+/// [`get_links`] doesn't include.
+pub fn get_wikilinks<'a>(ast: &'a Node<'a, RefCell<Ast>>) -> Vec<String> {
+    let links: RefCell<Vec<String>> = RefCell::new(vec![]);
+    iterate_wikilinks(ast, |l| links.borrow_mut().push(l.url.clone()));
+    let r = links.borrow().to_vec();
+    r
+}
+
 pub fn iterate_images<'a, F>(ast: &'a Node<'a, RefCell<Ast>>, iterator: F)
 where
     F: Fn(&mut NodeLink),
@@ -53,7 +227,7 @@ where
     });
 }
 
-pub fn replace_images<'a>(ast: &'a Node<'a, RefCell<Ast>>, from: &'a str, to: &'a str) {
+pub fn replace_images<'a>(ast: &'a Node<'a, RefCell<Ast>>, from: &str, to: &str) {
     iterate_images(ast, |l| {
         if l.url == from {
             l.url = String::from(to)
@@ -61,20 +235,283 @@ pub fn replace_images<'a>(ast: &'a Node<'a, RefCell<Ast>>, from: &'a str, to: &'
     });
 }
 
-pub fn remove_image<'a>(ast: &'a Node<'a, RefCell<Ast>>, url: &'a str) {
+pub fn rewrite_images<'a>(ast: &'a Node<'a, RefCell<Ast>>, rules: &Rules) {
+    iterate_images(ast, |l| {
+        if let Some(url) = rules.rewrite(&l.url) {
+            l.url = url
+        }
+    });
+}
+
+pub fn rebase_images<'a>(ast: &'a Node<'a, RefCell<Ast>>, rebase: &rebase::Rebase) {
+    iterate_images(ast, |l| {
+        if let Some(url) = rebase.rebase(&l.url) {
+            l.url = url
+        }
+    });
+}
+
+pub fn remove_image<'a>(ast: &'a Node<'a, RefCell<Ast>>, url: &str) {
+    remove_images_where(ast, Removal::Unlink, |u| u == url)
+}
+
+/// Removes the images whose URL `f` accepts, unlinking keeps their alt text.
+pub fn remove_images_where<'a, F>(ast: &'a Node<'a, RefCell<Ast>>, removal: Removal, f: F)
+where
+    F: Fn(&str) -> bool,
+{
+    remove_nodes(ast, removal, |v| match v {
+        NodeValue::Image(l) => f(&l.url),
+        _ => false,
+    })
+}
+
+fn remove_nodes<'a, F>(ast: &'a Node<'a, RefCell<Ast>>, removal: Removal, f: F)
+where
+    F: Fn(&NodeValue) -> bool,
+{
     utils::iter_nodes(ast, &|node| {
-        if let NodeValue::Image(ref mut l) = &mut node.data.borrow_mut().value {
-            if l.url == url {
+        if f(&node.data.borrow().value) {
+            if removal == Removal::Unlink {
                 node.children().for_each(|n| node.insert_before(n));
-                node.detach();
             }
+            node.detach();
         }
     })
 }
 
+/// Whether `url` is an absolute `http` or `https` URL to another site than
+/// `base`, when given.
+pub fn is_external(url: &str, base: Option<&url::Url>) -> bool {
+    match url::Url::parse(url) {
+        Ok(u) if matches!(u.scheme(), "http" | "https") => {
+            base.is_none_or(|b| b.host_str() != u.host_str())
+        }
+        _ => false,
+    }
+}
+
+/// Replaces the alt text of the images with the URL `url`, which are
+/// otherwise kept as they are.
+pub fn set_image_alt<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    ast: &'a AstNode<'a>,
+    url: &str,
+    alt: &str,
+) {
+    for node in ast.descendants().collect::<Vec<_>>() {
+        if !matches!(&node.data.borrow().value, NodeValue::Image(l) if l.url == url) {
+            continue;
+        }
+        node.children().for_each(|c| c.detach());
+        node.append(arena.alloc(Node::new(RefCell::from(Ast::new(
+            NodeValue::Text(String::from(alt)),
+            LineColumn { line: 0, column: 0 },
+        )))));
+    }
+}
+
+pub fn set_image_title<'a>(ast: &'a Node<'a, RefCell<Ast>>, url: &str, title: &str) {
+    iterate_images(ast, |l| {
+        if l.url == url {
+            l.title = String::from(title)
+        }
+    });
+}
+
 pub fn get_images<'a>(ast: &'a Node<'a, RefCell<Ast>>) -> Vec<String> {
     let images: RefCell<Vec<String>> = RefCell::new(vec![]);
     iterate_images(ast, |l| images.borrow_mut().push(l.url.clone()));
     let r = images.borrow().to_vec();
     r
 }
+
+/// Lists the links and wikilinks of the document, in order, with their text,
+/// kind and position. `source` is the Markdown the AST was parsed from.
+pub fn list_links<'a>(ast: &'a AstNode<'a>, source: &str) -> Vec<LinkInfo> {
+    list(ast, source, false)
+        .into_iter()
+        .map(|l| LinkInfo {
+            url: l.url,
+            text: l.text,
+            title: l.title,
+            kind: l.kind,
+            line: l.line,
+            column: l.column,
+            heading: l.heading,
+        })
+        .collect()
+}
+
+/// Lists the images of the document, like [`list_links`].
+pub fn list_images<'a>(ast: &'a AstNode<'a>, source: &str) -> Vec<ImageInfo> {
+    list(ast, source, true)
+        .into_iter()
+        .map(|l| ImageInfo {
+            url: l.url,
+            alt: l.text,
+            title: l.title,
+            kind: l.kind,
+            line: l.line,
+            column: l.column,
+            heading: l.heading,
+        })
+        .collect()
+}
+
+fn list<'a>(ast: &'a AstNode<'a>, source: &str, images: bool) -> Vec<LinkInfo> {
+    let lines = Lines::new(source, splice::frontmatter_lines(ast));
+    // Where the search for bare URLs in each block should continue from
+    let mut cursors: HashMap<*const AstNode<'a>, usize> = HashMap::new();
+    let mut heading = None;
+    let mut list = vec![];
+
+    for node in ast.descendants() {
+        let value = node.data.borrow().value.clone();
+        let (url, title) = match &value {
+            NodeValue::Heading(_) => {
+                heading = Some(text(node));
+                continue;
+            }
+            NodeValue::Image(l) if images => (l.url.clone(), l.title.clone()),
+            NodeValue::Link(l) if !images => (l.url.clone(), l.title.clone()),
+            NodeValue::WikiLink(l) if !images => (l.url.clone(), String::new()),
+            _ => continue,
+        };
+        let text = text(node);
+
+        let sourcepos = node.data.borrow().sourcepos;
+        let (kind, offset) = match lines.locate(sourcepos, |s| Span::parse(s, &value)) {
+            Some((start, span)) => {
+                let kind = match span.kind {
+                    SpanKind::Inline { .. } => LinkKind::Inline,
+                    SpanKind::Reference { .. } => LinkKind::Reference,
+                    SpanKind::Autolink | SpanKind::Bare => LinkKind::Autolink,
+                    SpanKind::Wikilink { .. } => LinkKind::Wikilink,
+                };
+                (kind, start)
+            }
+            // Bare URLs found by the autolink extension don't have a
+            // position, so they are searched in their block's source
+            None => {
+                let block = node
+                    .ancestors()
+                    .find(|a| a.data.borrow().sourcepos.start.line != 0);
+                let offset = block.and_then(|b| {
+                    let pos = b.data.borrow().sourcepos;
+                    let start = lines.offset(pos.start.line, pos.start.column)?;
+                    let cursor = cursors.entry(b as *const _).or_insert(start);
+                    let found = *cursor + source.get(*cursor..)?.find(&text)?;
+                    *cursor = found + text.len();
+                    Some(found)
+                });
+                (LinkKind::Autolink, offset.unwrap_or(0))
+            }
+        };
+        let (line, column) = lines.position(offset);
+
+        list.push(LinkInfo {
+            url,
+            text,
+            title: Some(title).filter(|t| !t.is_empty()),
+            kind,
+            line,
+            column,
+            heading: heading.clone(),
+        });
+    }
+
+    list
+}
+
+pub(crate) fn text<'a>(node: &'a AstNode<'a>) -> String {
+    node.descendants()
+        .filter_map(|n| match &n.data.borrow().value {
+            NodeValue::Text(t) => Some(t.clone()),
+            NodeValue::Code(c) => Some(c.literal.clone()),
+            NodeValue::SoftBreak | NodeValue::LineBreak => Some(String::from(" ")),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use comrak::Arena;
+
+    use super::rewrite::{Match, Pattern};
+    use super::{list_images, list_links, LinkKind, Removal};
+    use crate::utils;
+
+    #[test]
+    fn list_with_positions() {
+        let markdown = "---\n\
+            title: Test\n\
+            ---\n\
+            \n\
+            A [*small* link](https://guz.one \"Title\") and <https://example.com>\n\
+            \n\
+            ## Section `one`\n\
+            \n\
+            - [Reference][ref], [[Page|alias]] and ![An image](cat.png)\n\
+            \n\
+            [ref]: https://guz.one/ref\n";
+        let mut options = utils::default_options();
+        options.extension.wikilinks_title_after_pipe = true;
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, markdown, &options);
+
+        let links = list_links(ast, markdown);
+        let summary = links
+            .iter()
+            .map(|l| (l.url.as_str(), l.text.as_str(), l.kind, l.line, l.column))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("https://guz.one", "small link", LinkKind::Inline, 5, 3),
+                (
+                    "https://example.com",
+                    "https://example.com",
+                    LinkKind::Autolink,
+                    5,
+                    47
+                ),
+                (
+                    "https://guz.one/ref",
+                    "Reference",
+                    LinkKind::Reference,
+                    9,
+                    3
+                ),
+                ("Page", "alias", LinkKind::Wikilink, 9, 21),
+            ]
+        );
+        assert_eq!(links[0].title.as_deref(), Some("Title"));
+        assert_eq!(links[0].heading, None);
+        assert_eq!(links[3].heading.as_deref(), Some("Section one"));
+
+        let images = list_images(ast, markdown);
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].alt, "An image");
+        assert_eq!((images[0].line, images[0].column), (9, 40));
+    }
+
+    #[test]
+    fn remove_by_pattern() {
+        let markdown = "Read [this](https://ext.com/a), [mine](https://guz.one/b) and \
+            [other](https://blog.guz.one/c) ![pic](https://ext.com/p.png)\n";
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, markdown, &utils::default_options());
+
+        let base = url::Url::parse("https://guz.one").unwrap();
+        super::remove_links_where(ast, Removal::Delete, |u| super::is_external(u, Some(&base)));
+        let host = Pattern::new(Match::Host, "GUZ.one").unwrap();
+        super::remove_links_where(ast, Removal::Unlink, |u| host.matches(u));
+        super::remove_images_where(ast, Removal::Unlink, |u| u.ends_with(".png"));
+
+        let mut out = vec![];
+        comrak::format_commonmark(ast, &utils::default_options(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Read , mine and  pic\n");
+    }
+}
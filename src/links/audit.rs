@@ -0,0 +1,139 @@
+use std::fmt::Display;
+
+use comrak::nodes::AstNode;
+use serde::Serialize;
+
+/// Title that marks an image as decorative, so its empty alt text is
+/// intended: `![](divider.png "decorative")`.
+pub const DECORATIVE: &str = "decorative";
+
+/// Alt text limit of Tumblr's image blocks.
+pub const MAX_ALT: usize = 4096;
+
+/// An accessibility problem of an image's alt text.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Problem {
+    /// No alt text, and no decorative marker
+    Empty,
+    /// The alt text is the file's name, or a camera's default name
+    Filename,
+    /// The alt text says the image is decorative, instead of being empty and
+    /// marked as [`DECORATIVE`]
+    UnmarkedDecorative,
+    /// The alt text has more characters than the limit
+    TooLong(usize),
+}
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Empty => write!(f, "missing alt text"),
+            Problem::Filename => write!(f, "alt text is a file name"),
+            Problem::UnmarkedDecorative => {
+                write!(f, "decorative image without the \"{}\" title", DECORATIVE)
+            }
+            Problem::TooLong(len) => write!(f, "alt text has {} characters", len),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    pub url: String,
+    pub alt: String,
+    pub line: usize,
+    pub column: usize,
+    pub problem: Problem,
+}
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} ({})",
+            self.line, self.column, self.url, self.problem
+        )
+    }
+}
+
+/// Reports the images whose alt text is missing, a file name, says the image
+/// is decorative without the marker, or has more than `max_alt` characters.
+pub fn audit_images<'a>(ast: &'a AstNode<'a>, source: &str, max_alt: usize) -> Vec<Issue> {
+    let filename = regex::Regex::new(
+        r"(?i)^(\S+\.(png|jpe?g|gif|webp|avif|svg|bmp|tiff?|heic)|(img|dsc|dscn|pxl|screenshot|image|photo)[\s_-]*[\d_-]*)$",
+    )
+    .unwrap();
+
+    super::list_images(ast, source)
+        .into_iter()
+        .filter_map(|image| {
+            let alt = image.alt.trim();
+            let marked = image
+                .title
+                .as_deref()
+                .is_some_and(|t| t.eq_ignore_ascii_case(DECORATIVE));
+            let stem = image
+                .url
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.split(['.', '?', '#']).next())
+                .unwrap_or("");
+            let len = alt.chars().count();
+
+            let problem = if alt.is_empty() {
+                (!marked).then_some(Problem::Empty)?
+            } else if filename.is_match(alt) || alt.eq_ignore_ascii_case(stem) {
+                Problem::Filename
+            } else if ["decorative", "decoration", "spacer", "divider", "separator"]
+                .iter()
+                .any(|d| alt.eq_ignore_ascii_case(d))
+            {
+                Problem::UnmarkedDecorative
+            } else if len > max_alt {
+                Problem::TooLong(len)
+            } else {
+                return None;
+            };
+
+            Some(Issue {
+                alt: image.alt,
+                url: image.url,
+                line: image.line,
+                column: image.column,
+                problem,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use comrak::Arena;
+
+    use super::{audit_images, Problem};
+    use crate::utils;
+
+    #[test]
+    fn audit_alt_text() {
+        let markdown = "![](a.png) ![](line.png \"decorative\") ![cat.JPG](cat.jpg)\n\
+            ![IMG_2041](x/y.jpg) ![Divider](div.png) ![A long description](dog.png)\n\
+            ![sunset](sunset.png?w=2) ![A cat sleeping](cat.png)\n";
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, markdown, &utils::default_options());
+
+        let issues = audit_images(ast, markdown, 15)
+            .into_iter()
+            .map(|i| (i.url, i.line, i.column, i.problem))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                (String::from("a.png"), 1, 1, Problem::Empty),
+                (String::from("cat.jpg"), 1, 39, Problem::Filename),
+                (String::from("x/y.jpg"), 2, 1, Problem::Filename),
+                (String::from("div.png"), 2, 22, Problem::UnmarkedDecorative),
+                (String::from("dog.png"), 2, 42, Problem::TooLong(18)),
+                (String::from("sunset.png?w=2"), 3, 1, Problem::Filename),
+            ]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{Anchorizer, Arena};
+use serde::Serialize;
+
+use super::LinkKind;
+use crate::{files, utils};
+
+/// Why a link is broken.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "reason", content = "target")]
+pub enum Reason {
+    /// The file or directory the link points to doesn't exist
+    MissingFile(PathBuf),
+    /// No heading of the target document has the fragment's slug
+    MissingAnchor(String),
+}
+impl Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::MissingFile(p) => write!(f, "{} not found", p.display()),
+            Reason::MissingAnchor(a) => write!(f, "no heading for #{}", a),
+        }
+    }
+}
+
+/// A broken link or image, and where it is in the document.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Broken {
+    pub url: String,
+    pub image: bool,
+    pub kind: LinkKind,
+    pub line: usize,
+    pub column: usize,
+    #[serde(flatten)]
+    pub reason: Reason,
+}
+impl Display for Broken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} ({})",
+            self.line, self.column, self.url, self.reason
+        )
+    }
+}
+
+/// Checks the relative links and images of the document, without any network
+/// access. Files are looked up relative to `dir`, the document's directory,
+/// and fragments must match the slug of a heading in the target Markdown
+/// file, or in the document itself for links like `#section`. Links with a
+/// scheme, root relative links and wikilinks aren't checked.
+pub fn check<'a>(
+    ast: &'a AstNode<'a>,
+    source: &str,
+    dir: &Path,
+    options: &comrak::Options,
+) -> Vec<Broken> {
+    let own = headings(ast);
+    let mut slugs: HashMap<PathBuf, Option<HashSet<String>>> = HashMap::new();
+    let links = super::list_links(ast, source)
+        .into_iter()
+        .map(|l| (l.url, false, l.kind, l.line, l.column));
+    let images = super::list_images(ast, source)
+        .into_iter()
+        .map(|i| (i.url, true, i.kind, i.line, i.column));
+
+    links
+        .chain(images)
+        .filter(|(url, _, kind, ..)| *kind != LinkKind::Wikilink && is_relative(url))
+        .filter_map(|(url, image, kind, line, column)| {
+            let (path, fragment) = match url.split_once('#') {
+                Some((p, f)) => (p, Some(f)),
+                None => (url.as_str(), None),
+            };
+            let path = path.split('?').next().unwrap_or("");
+            let path = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+
+            let reason = if path.is_empty() {
+                let fragment = fragment.filter(|f| !f.is_empty())?;
+                (!own.contains(fragment)).then(|| Reason::MissingAnchor(String::from(fragment)))
+            } else {
+                let target = dir.join(path.as_ref());
+                if !target.exists() {
+                    Some(Reason::MissingFile(target))
+                } else {
+                    fragment
+                        .filter(|f| !f.is_empty() && is_markdown(&target))
+                        .filter(|f| {
+                            slugs
+                                .entry(target.clone())
+                                .or_insert_with(|| file_headings(&target, options))
+                                .as_ref()
+                                .is_some_and(|s| !s.contains(*f))
+                        })
+                        .map(|f| Reason::MissingAnchor(String::from(f)))
+                }
+            }?;
+
+            Some(Broken {
+                url,
+                image,
+                kind,
+                line,
+                column,
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// URLs without a scheme, that aren't root or protocol relative.
+fn is_relative(url: &str) -> bool {
+    url::Url::parse(url).is_err() && !url.starts_with('/')
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| files::MARKDOWN_EXTENSIONS.contains(&e))
+}
+
+/// Slugs of the document's headings, like the ids comrak gives them.
+pub fn headings<'a>(ast: &'a AstNode<'a>) -> HashSet<String> {
+    let mut anchorizer = Anchorizer::new();
+    ast.descendants()
+        .filter(|n| matches!(n.data.borrow().value, NodeValue::Heading(_)))
+        .map(|n| anchorizer.anchorize(super::text(n)))
+        .collect()
+}
+
+/// Headings of a Markdown file, or `None` when it can't be read, in which case
+/// fragments pointing to it aren't checked.
+fn file_headings(path: &Path, options: &comrak::Options) -> Option<HashSet<String>> {
+    let source = std::fs::read_to_string(path).ok()?;
+    let arena = Arena::new();
+    Some(headings(utils::parse_document(&arena, &source, options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use comrak::Arena;
+
+    use super::{check, Reason};
+    use crate::utils;
+
+    #[test]
+    fn check_local_links() {
+        let dir = std::env::temp_dir().join(format!("mdparser-check-{}", std::process::id()));
+        fs::create_dir_all(dir.join("images")).unwrap();
+        fs::write(dir.join("other.md"), "# Other post\n\n## Some Section\n").unwrap();
+        fs::write(dir.join("images/cat.png"), "").unwrap();
+
+        let markdown = "# Title\n\
+            \n\
+            [ok](other.md#some-section), [renamed](draft.md), [anchor](other.md#gone)\n\
+            [self](#title), [missing](#nothing) and [web](https://guz.one/missing)\n\
+            \n\
+            ![Cat](images/cat.png) ![Dog](images/dog%20one.png)\n";
+        let options = utils::default_options();
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, markdown, &options);
+
+        let broken = check(ast, markdown, &dir, &options)
+            .into_iter()
+            .map(|b| (b.url, b.line, b.column, b.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            broken,
+            vec![
+                (
+                    String::from("draft.md"),
+                    3,
+                    30,
+                    Reason::MissingFile(dir.join("draft.md"))
+                ),
+                (
+                    String::from("other.md#gone"),
+                    3,
+                    51,
+                    Reason::MissingAnchor(String::from("gone"))
+                ),
+                (
+                    String::from("#nothing"),
+                    4,
+                    17,
+                    Reason::MissingAnchor(String::from("nothing"))
+                ),
+                (
+                    String::from("images/dog%20one.png"),
+                    6,
+                    24,
+                    Reason::MissingFile(dir.join("images/dog one.png"))
+                ),
+            ]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
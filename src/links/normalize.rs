@@ -0,0 +1,276 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// How URLs are canonicalized, usually set in the `[normalize]` section of
+/// the project's configuration. Only URLs with a scheme and host are changed,
+/// and the parts that aren't normalized are kept as written.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Options {
+    /// Query parameters to remove, where a `*` at the end matches any suffix
+    pub strip_params: Vec<String>,
+    pub lowercase_host: bool,
+    /// Remove `:80` from `http` URLs and `:443` from `https` ones
+    pub drop_default_port: bool,
+    /// Remove a `#` without anything after it
+    pub drop_empty_fragment: bool,
+    /// Hosts whose `http` URLs are upgraded to `https`, where `*.` at the start
+    /// matches any subdomain
+    pub https_hosts: Vec<String>,
+    /// Redirectors whose target URL is taken out of their query
+    pub redirects: Vec<Redirect>,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            strip_params: [
+                "utm_*", "fbclid", "gclid", "dclid", "msclkid", "igshid", "mc_cid", "mc_eid", "si",
+                "ref_src", "_hsenc", "_hsmi",
+            ]
+            .map(String::from)
+            .to_vec(),
+            lowercase_host: true,
+            drop_default_port: true,
+            drop_empty_fragment: true,
+            https_hosts: [
+                "tumblr.com",
+                "*.tumblr.com",
+                "youtube.com",
+                "www.youtube.com",
+                "youtu.be",
+                "twitter.com",
+                "x.com",
+                "github.com",
+                "*.wikipedia.org",
+            ]
+            .map(String::from)
+            .to_vec(),
+            redirects: vec![Redirect {
+                host: String::from("t.umblr.com"),
+                path: String::from("/redirect"),
+                param: String::from("z"),
+            }],
+        }
+    }
+}
+
+/// A redirector, like `https://t.umblr.com/redirect?z=<url>`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Redirect {
+    pub host: String,
+    pub path: String,
+    /// Query parameter with the target URL
+    pub param: String,
+}
+
+impl Options {
+    pub fn normalize(&self, url: &str) -> String {
+        let url = self
+            .unwrap(url)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| String::from(url));
+        let Some(scheme_end) = url.find("://") else {
+            return url;
+        };
+        let mut scheme = url[..scheme_end].to_lowercase();
+        let rest = &url[scheme_end + 3..];
+
+        let (rest, fragment) = match rest.split_once('#') {
+            Some((r, f)) => (r, Some(f)),
+            None => (rest, None),
+        };
+        let (rest, query) = match rest.split_once('?') {
+            Some((r, q)) => (r, Some(q)),
+            None => (rest, None),
+        };
+        let authority_end = rest.find('/').unwrap_or(rest.len());
+        let (authority, path) = rest.split_at(authority_end);
+        let (userinfo, host_port) = match authority.rfind('@') {
+            Some(i) => authority.split_at(i + 1),
+            None => ("", authority),
+        };
+        let (host, port) = match host_port.rfind(':') {
+            Some(i) if !host_port[i..].contains(']') => host_port.split_at(i),
+            _ => (host_port, ""),
+        };
+
+        let host = if self.lowercase_host {
+            host.to_lowercase()
+        } else {
+            String::from(host)
+        };
+        let default_port = match scheme.as_str() {
+            "http" => ":80",
+            "https" => ":443",
+            _ => "",
+        };
+        let mut port = if self.drop_default_port && port == default_port {
+            ""
+        } else {
+            port
+        };
+        if scheme == "http" && self.https_hosts.iter().any(|h| host_matches(h, &host)) {
+            scheme = String::from("https");
+            if port == ":80" {
+                port = "";
+            }
+        }
+
+        let query = query
+            .map(|q| {
+                q.split('&')
+                    .filter(|param| {
+                        let key = param.split('=').next().unwrap_or(param);
+                        !self.strip_params.iter().any(|p| match p.strip_suffix('*') {
+                            Some(prefix) => key.starts_with(prefix),
+                            None => key == p,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+                    .join("&")
+            })
+            .filter(|q| !q.is_empty());
+        let fragment = fragment.filter(|f| !(self.drop_empty_fragment && f.is_empty()));
+
+        let mut out = format!("{}://{}{}{}{}", scheme, userinfo, host, port, path);
+        if let Some(q) = query {
+            out.push('?');
+            out.push_str(&q);
+        }
+        if let Some(f) = fragment {
+            out.push('#');
+            out.push_str(f);
+        }
+        out
+    }
+
+    /// Why the URL can't be fully normalized, for redirectors whose target
+    /// can't be found. [`Options::normalize`] keeps these as they are.
+    pub fn problem(&self, url: &str) -> Option<Problem> {
+        self.unwrap(url).err()
+    }
+
+    /// The target of a redirector URL, followed until it isn't one.
+    fn unwrap(&self, url: &str) -> Result<Option<String>, Problem> {
+        let mut target: Option<String> = None;
+        for _ in 0..8 {
+            let Ok(parsed) = url::Url::parse(target.as_deref().unwrap_or(url)) else {
+                return match target {
+                    Some(t) => Err(Problem::InvalidTarget(t)),
+                    None => Ok(None),
+                };
+            };
+            let redirect = self
+                .redirects
+                .iter()
+                .find(|r| parsed.host_str() == Some(r.host.as_str()) && parsed.path() == r.path);
+            let Some(redirect) = redirect else {
+                return Ok(target);
+            };
+            let next = parsed
+                .query_pairs()
+                .find(|(k, _)| *k == redirect.param)
+                .map(|(_, v)| v.into_owned())
+                .ok_or(Problem::MissingTarget)?;
+            target = Some(next);
+        }
+        Err(Problem::TooManyRedirects)
+    }
+}
+
+/// Why a URL couldn't be normalized.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "problem", content = "target")]
+pub enum Problem {
+    /// A redirector without its target parameter
+    MissingTarget,
+    /// The target of a redirector isn't a URL
+    InvalidTarget(String),
+    /// Redirectors that still redirect after 8 of them
+    TooManyRedirects,
+}
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingTarget => write!(f, "redirect without a target"),
+            Problem::InvalidTarget(t) => write!(f, "redirect to an invalid URL, {}", t),
+            Problem::TooManyRedirects => write!(f, "too many redirects"),
+        }
+    }
+}
+
+/// A link or image that couldn't be normalized.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Failure {
+    pub url: String,
+    pub image: bool,
+    #[serde(flatten)]
+    pub problem: Problem,
+}
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.url, self.problem)
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.')),
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Options, Problem};
+
+    #[test]
+    fn normalize_urls() {
+        let options = Options::default();
+        let normalize = |url| options.normalize(url);
+
+        assert_eq!(
+            normalize("https://Example.COM:443/Path?utm_source=app&id=3&fbclid=abc#"),
+            "https://example.com/Path?id=3"
+        );
+        assert_eq!(
+            normalize("http://www.youtube.com:80/watch?v=abc&si=xyz#t=1"),
+            "https://www.youtube.com/watch?v=abc#t=1"
+        );
+        assert_eq!(
+            normalize("http://guz.tumblr.com/post/1"),
+            "https://guz.tumblr.com/post/1"
+        );
+        assert_eq!(
+            normalize(
+                "https://t.umblr.com/redirect?z=http%3A%2F%2Fexample.com%3A8080%2F%3Futm_medium%3Dx&t=abc"
+            ),
+            "http://example.com:8080/"
+        );
+        assert_eq!(normalize("posts/other.md#"), "posts/other.md#");
+
+        let redirect = "https://t.umblr.com/redirect?t=abc";
+        assert_eq!(options.problem(redirect), Some(Problem::MissingTarget));
+        assert_eq!(normalize(redirect), redirect);
+        assert_eq!(
+            options.problem("https://t.umblr.com/redirect?z=nope"),
+            Some(Problem::InvalidTarget(String::from("nope")))
+        );
+        assert_eq!(options.problem("https://guz.one/"), None);
+
+        let options = Options {
+            strip_params: vec![],
+            lowercase_host: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            options.normalize("https://Example.com/?si=1"),
+            "https://Example.com/?si=1"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Moves relative URLs from one directory to another, so they point to the
+/// same files. Paths are compared as written, without following symlinks.
+#[derive(Debug, Clone)]
+pub struct Rebase {
+    /// Directory the document is in
+    from: PathBuf,
+    /// Directory the document is moved to
+    to: PathBuf,
+    /// Directory the linked files moved from, and the one they moved to
+    assets: Option<(PathBuf, PathBuf)>,
+}
+impl Rebase {
+    pub fn new(from: &Path, to: &Path) -> Self {
+        Self {
+            from: absolute(from),
+            to: absolute(to),
+            assets: None,
+        }
+    }
+
+    /// Also points the URLs of files inside `old` to the same files inside
+    /// `new`.
+    pub fn with_assets(mut self, old: &Path, new: &Path) -> Self {
+        self.assets = Some((absolute(old), absolute(new)));
+        self
+    }
+
+    /// The URL resolving to the same file from the new directory, or `None`
+    /// for absolute, root relative and fragment only URLs, and URLs that
+    /// don't change.
+    pub fn rebase(&self, url: &str) -> Option<String> {
+        if url::Url::parse(url).is_ok() || url.starts_with('/') {
+            return None;
+        }
+        let (path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+        if path.is_empty() {
+            return None;
+        }
+
+        let mut target = normalize(&self.from.join(path));
+        let moved = match &self.assets {
+            Some((old, new)) => match target.strip_prefix(old) {
+                Ok(rest) => Some(new.join(rest)),
+                Err(_) => None,
+            },
+            None => None,
+        };
+        match moved {
+            Some(moved) => target = moved,
+            // Keeps the URL as written when it doesn't change
+            None if self.from == self.to => return None,
+            None => {}
+        }
+
+        let mut rebased = relative(&self.to, &target);
+        if path.ends_with('/') && !rebased.ends_with('/') {
+            rebased.push('/');
+        }
+        Some(rebased + suffix)
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+/// Resolves `.` and `..` without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Path of `target` relative to `dir`, with `/` separators.
+fn relative(dir: &Path, target: &Path) -> String {
+    let dir = dir.components().collect::<Vec<_>>();
+    let target = target.components().collect::<Vec<_>>();
+    let common = dir.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+    let parts = std::iter::repeat_n(String::from(".."), dir.len() - common)
+        .chain(
+            target[common..]
+                .iter()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+        )
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        String::from(".")
+    } else {
+        parts.join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Rebase;
+
+    #[test]
+    fn rebase_paths() {
+        let rebase = Rebase::new(Path::new("/blog/drafts"), Path::new("/blog/posts/2024"));
+        assert_eq!(
+            rebase.rebase("images/cat.png").as_deref(),
+            Some("../../drafts/images/cat.png")
+        );
+        assert_eq!(
+            rebase.rebase("./../posts/2024/other.md#intro").as_deref(),
+            Some("other.md#intro")
+        );
+        assert_eq!(
+            rebase.rebase("../assets/?v=2").as_deref(),
+            Some("../../assets/?v=2")
+        );
+        assert_eq!(rebase.rebase("https://guz.one/cat.png"), None);
+        assert_eq!(rebase.rebase("/images/cat.png"), None);
+        assert_eq!(rebase.rebase("#section"), None);
+
+        let rebase = Rebase::new(Path::new("/blog/drafts"), Path::new("/blog/drafts")).with_assets(
+            Path::new("/blog/drafts/images"),
+            Path::new("/blog/static/img"),
+        );
+        assert_eq!(
+            rebase.rebase("images/2024/cat.png").as_deref(),
+            Some("../static/img/2024/cat.png")
+        );
+        assert_eq!(rebase.rebase("./other.md"), None);
+    }
+}
@@ -0,0 +1,440 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use comrak::arena_tree::Node;
+use comrak::nodes::{Ast, AstNode, LineColumn, NodeHtmlBlock, NodeLink, NodeValue};
+use comrak::Arena;
+
+use crate::splice::{self, Lines, Span, SpanKind, SpliceErr};
+
+/// A link reference definition, `[label]: url "title"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    /// The label as written
+    pub label: String,
+    pub url: String,
+    pub title: String,
+    /// Range of the definition's line in the source, without its newline
+    pub range: Range<usize>,
+}
+impl Definition {
+    pub(crate) fn format(label: &str, url: &str, title: &str) -> String {
+        format!(
+            "[{}]: {}{}",
+            label,
+            splice::format_destination(url),
+            splice::format_title(title)
+        )
+    }
+}
+
+static DEFINITION: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(
+        r#"^ {0,3}\[((?:[^\\\[\]]|\\.)+)\]:[ \t]*(<(?:[^\\<>]|\\.)*>|\S+)(?:[ \t]+("(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'|\((?:[^()\\]|\\.)*\)))?[ \t]*$"#,
+    )
+    .unwrap()
+});
+
+/// Finds the single line link reference definitions of `source`, skipping
+/// its first `skip` bytes, used for the frontmatter, and fenced code blocks.
+pub fn definitions(source: &str, skip: usize) -> Vec<Definition> {
+    let mut definitions: Vec<Definition> = vec![];
+    let mut fence: Option<&str> = None;
+    let mut start = skip;
+    for line in source.get(skip..).unwrap_or("").split_inclusive('\n') {
+        let range = start..start + line.trim_end_matches(['\n', '\r']).len();
+        start += line.len();
+        let line = &source[range.clone()];
+
+        let trimmed = line.trim_start();
+        if let Some(f) = fence {
+            if trimmed.starts_with(f) {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(f) = ["```", "~~~"].into_iter().find(|f| trimmed.starts_with(f)) {
+            fence = Some(f);
+            continue;
+        }
+
+        let Some(captures) = DEFINITION.captures(line) else {
+            continue;
+        };
+        let label = String::from(&captures[1]);
+        if definitions
+            .iter()
+            .any(|d| normalize(&d.label) == normalize(&label))
+        {
+            continue;
+        }
+        let url = &captures[2];
+        let url = url
+            .strip_prefix('<')
+            .and_then(|u| u.strip_suffix('>'))
+            .unwrap_or(url);
+        let title = captures
+            .get(3)
+            .map(|t| unescape(&t.as_str()[1..t.len() - 1]))
+            .unwrap_or_default();
+        definitions.push(Definition {
+            label,
+            url: unescape(url),
+            title,
+            range,
+        });
+    }
+    definitions
+}
+
+/// Labels match case-insensitively, with any whitespace collapsed.
+pub fn normalize(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(n) if c == '\\' && n.is_ascii_punctuation() => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Label of a reference link, from its text in the source.
+pub(crate) fn label(text: &str, open: usize, close: usize) -> &str {
+    match &text[close + 1..] {
+        "" | "[]" => &text[open..close],
+        rest => &rest[1..rest.len() - 1],
+    }
+}
+
+/// Length of the frontmatter at the start of the source.
+fn frontmatter_len<'a>(ast: &'a AstNode<'a>) -> usize {
+    match ast.first_child().map(|c| c.data.borrow().value.clone()) {
+        Some(NodeValue::FrontMatter(f)) => f.len(),
+        _ => 0,
+    }
+}
+
+/// The reference links and images of a document, and its definitions, before
+/// it is changed.
+///
+/// comrak resolves reference links into inline ones and drops the
+/// definitions, so after mutating the AST, [`References::restore`] writes them
+/// back as `[text][label]` with their definitions, updated with the links'
+/// current URLs.
+#[derive(Debug)]
+pub struct References<'a> {
+    root: &'a AstNode<'a>,
+    definitions: Vec<Definition>,
+    /// Nodes with their normalized label, and how they were closed, like
+    /// `][label]`, `][]` or `]`
+    links: Vec<(&'a AstNode<'a>, String, String)>,
+}
+
+pub fn snapshot<'a>(ast: &'a AstNode<'a>, source: &str) -> References<'a> {
+    let definitions = definitions(source, frontmatter_len(ast));
+    let lines = Lines::new(source, splice::frontmatter_lines(ast));
+    let links = ast
+        .descendants()
+        .filter_map(|node| {
+            let value = node.data.borrow().value.clone();
+            if !matches!(value, NodeValue::Link(_) | NodeValue::Image(_)) {
+                return None;
+            }
+            let (start, span) =
+                lines.locate(node.data.borrow().sourcepos, |s| Span::parse(s, &value))?;
+            let SpanKind::Reference { open, close } = span.kind else {
+                return None;
+            };
+            let text = &source[start..start + span.len];
+            Some((
+                node,
+                normalize(label(text, open, close)),
+                String::from(&text[close..]),
+            ))
+        })
+        .collect();
+
+    References {
+        root: ast,
+        definitions,
+        links,
+    }
+}
+
+impl<'a> References<'a> {
+    /// Turns the reference links still in the document back into their
+    /// reference form, and adds the definitions to the end of the document.
+    /// Definitions are dropped once none of their links are left, and links
+    /// that no longer match the URL of others with the same label stay inline.
+    pub fn restore(&self, arena: &'a Arena<AstNode<'a>>) {
+        let mut assigned: HashMap<&str, NodeLink> = HashMap::new();
+        for (node, label, close) in &self.links {
+            if !node.ancestors().any(|a| std::ptr::eq(a, self.root)) {
+                continue;
+            }
+            let (link, open) = match &node.data.borrow().value {
+                NodeValue::Link(l) => (l.clone(), "["),
+                NodeValue::Image(l) => (l.clone(), "!["),
+                _ => continue,
+            };
+            if !self
+                .definitions
+                .iter()
+                .any(|d| normalize(&d.label) == *label)
+            {
+                continue;
+            }
+            match assigned.get(label.as_str()) {
+                Some(l) if l.url != link.url || l.title != link.title => continue,
+                Some(_) => {}
+                None => {
+                    assigned.insert(label, link);
+                }
+            }
+
+            node.insert_before(html(arena, NodeValue::HtmlInline(String::from(open))));
+            node.children().for_each(|c| node.insert_before(c));
+            node.insert_before(html(arena, NodeValue::HtmlInline(close.clone())));
+            node.detach();
+        }
+
+        let referenced: HashSet<&str> = self.links.iter().map(|(_, l, _)| l.as_str()).collect();
+        let definitions = self
+            .definitions
+            .iter()
+            .filter_map(|d| {
+                let label = normalize(&d.label);
+                match assigned.get(label.as_str()) {
+                    Some(l) => Some(Definition::format(&d.label, &l.url, &l.title)),
+                    None if !referenced.contains(label.as_str()) => {
+                        Some(Definition::format(&d.label, &d.url, &d.title))
+                    }
+                    None => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        if !definitions.is_empty() {
+            self.root.append(html(
+                arena,
+                NodeValue::HtmlBlock(NodeHtmlBlock {
+                    block_type: 0,
+                    literal: definitions.join("\n") + "\n",
+                }),
+            ));
+        }
+    }
+}
+
+fn html<'a>(arena: &'a Arena<AstNode<'a>>, value: NodeValue) -> &'a AstNode<'a> {
+    arena.alloc(Node::new(RefCell::from(Ast::new(
+        value,
+        LineColumn { line: 0, column: 0 },
+    ))))
+}
+
+/// Rewrites the reference links and images of `source` as inline ones,
+/// removing the definitions they used.
+pub fn to_inline<'a>(ast: &'a AstNode<'a>, source: &str) -> Result<String, SpliceErr> {
+    let definitions = definitions(source, frontmatter_len(ast));
+    let lines = Lines::new(source, splice::frontmatter_lines(ast));
+    let mut used = HashSet::new();
+    let mut edits = vec![];
+
+    for node in ast.descendants() {
+        let value = node.data.borrow().value.clone();
+        let (NodeValue::Link(link) | NodeValue::Image(link)) = &value else {
+            continue;
+        };
+        let sourcepos = node.data.borrow().sourcepos;
+        let Some((start, span)) = lines.locate(sourcepos, |s| Span::parse(s, &value)) else {
+            continue;
+        };
+        let SpanKind::Reference { open, close } = span.kind else {
+            continue;
+        };
+        used.insert(normalize(label(
+            &source[start..start + span.len],
+            open,
+            close,
+        )));
+        edits.push((
+            start + close + 1..start + span.len,
+            format!(
+                "({}{})",
+                splice::format_destination(&link.url),
+                splice::format_title(&link.title)
+            ),
+        ));
+    }
+
+    for definition in definitions {
+        if !used.contains(&normalize(&definition.label)) {
+            continue;
+        }
+        let mut end = definition.range.end;
+        if source[end..].starts_with('\n') {
+            end += 1;
+        }
+        // Takes the blank line after the definition with it, unless it
+        // separates the previous paragraph from the next
+        let before = source[..definition.range.start].trim_end_matches(' ');
+        if (before.is_empty() || before.ends_with("\n\n")) && source[end..].starts_with('\n') {
+            end += 1;
+        }
+        edits.push((definition.range.start..end, String::new()));
+    }
+
+    let out = splice::apply(source, edits)?;
+    Ok(if source.ends_with('\n') {
+        format!("{}\n", out.trim_end_matches('\n'))
+    } else {
+        out
+    })
+}
+
+/// Rewrites the inline links and images of `source` as reference ones. Links
+/// with the same URL and title share a definition, reusing existing ones, and
+/// new definitions are numbered in the order they first appear, so converting
+/// again gives the same labels.
+pub fn to_reference<'a>(ast: &'a AstNode<'a>, source: &str) -> Result<String, SpliceErr> {
+    let existing = definitions(source, frontmatter_len(ast));
+    let lines = Lines::new(source, splice::frontmatter_lines(ast));
+    let mut labels: HashMap<(String, String), String> = HashMap::new();
+    for d in existing.iter().rev() {
+        labels.insert((d.url.clone(), d.title.clone()), d.label.clone());
+    }
+    let mut taken: HashSet<String> = existing.iter().map(|d| normalize(&d.label)).collect();
+    let mut next = 1;
+    let mut added = vec![];
+    let mut edits = vec![];
+
+    for node in ast.descendants() {
+        let value = node.data.borrow().value.clone();
+        let (NodeValue::Link(link) | NodeValue::Image(link)) = &value else {
+            continue;
+        };
+        let sourcepos = node.data.borrow().sourcepos;
+        let Some((start, span)) = lines.locate(sourcepos, |s| Span::parse(s, &value)) else {
+            continue;
+        };
+        let SpanKind::Inline { close, .. } = span.kind else {
+            continue;
+        };
+
+        let label = labels
+            .entry((link.url.clone(), link.title.clone()))
+            .or_insert_with(|| {
+                while taken.contains(&next.to_string()) {
+                    next += 1;
+                }
+                taken.insert(next.to_string());
+                added.push(Definition::format(
+                    &next.to_string(),
+                    &link.url,
+                    &link.title,
+                ));
+                next.to_string()
+            });
+        edits.push((start + close + 1..start + span.len, format!("[{}]", label)));
+    }
+
+    if !added.is_empty() {
+        let body = source.trim_end_matches('\n');
+        let after_definition = existing.last().is_some_and(|d| d.range.end == body.len());
+        let separator = match (body.is_empty(), after_definition) {
+            (true, _) => "",
+            (false, true) => "\n",
+            (false, false) => "\n\n",
+        };
+        edits.push((
+            body.len()..source.len(),
+            format!("{}{}\n", separator, added.join("\n")),
+        ));
+    }
+
+    splice::apply(source, edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use comrak::Arena;
+
+    use crate::{links, utils};
+
+    #[test]
+    fn references_round_trip() {
+        let markdown = "A [link][Guz], [another](https://example.com \"Ex\") and [same](https://example.com \"Ex\").\n\
+            \n\
+            ![Cat](cat.png) and [guz]\n\
+            \n\
+            ```text\n\
+            [code]: https://code.example\n\
+            ```\n\
+            \n\
+            [guz]: https://guz.one\n";
+        let options = utils::default_options();
+
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, markdown, &options);
+        let references = super::snapshot(ast, markdown);
+        links::replace_links(ast, "https://guz.one", "https://guz.tumblr.com");
+        references.restore(&arena);
+        let mut out = vec![];
+        comrak::format_commonmark(ast, &options, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "A [link][Guz], [another](https://example.com \"Ex\") and [same](https://example.com \"Ex\").\n\
+            \n\
+            ![Cat](cat.png) and [guz]\n\
+            \n\
+            ``` text\n\
+            [code]: https://code.example\n\
+            ```\n\
+            \n\
+            [guz]: https://guz.tumblr.com\n"
+        );
+
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, markdown, &options);
+        let reference = super::to_reference(ast, markdown).unwrap();
+        assert_eq!(
+            reference,
+            "A [link][Guz], [another][1] and [same][1].\n\
+            \n\
+            ![Cat][2] and [guz]\n\
+            \n\
+            ```text\n\
+            [code]: https://code.example\n\
+            ```\n\
+            \n\
+            [guz]: https://guz.one\n\
+            [1]: https://example.com \"Ex\"\n\
+            [2]: cat.png\n"
+        );
+
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, &reference, &options);
+        assert_eq!(
+            super::to_inline(ast, &reference).unwrap(),
+            "A [link](https://guz.one), [another](https://example.com \"Ex\") and [same](https://example.com \"Ex\").\n\
+            \n\
+            ![Cat](cat.png) and [guz](https://guz.one)\n\
+            \n\
+            ```text\n\
+            [code]: https://code.example\n\
+            ```\n"
+        );
+    }
+}
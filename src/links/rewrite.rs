@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde_yaml as yaml;
+
+#[derive(Debug)]
+pub enum RewriteErr {
+    Glob(glob::PatternError),
+    Regex(regex::Error),
+    Io {
+        path: PathBuf,
+        err: std::io::Error,
+    },
+    Parsing(yaml::Error),
+    /// A rule in a rules file isn't a string to string pair
+    InvalidRule(yaml::Value),
+}
+
+/// How a rule's `from` matches a URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Match {
+    /// The whole URL
+    Exact,
+    /// The start of the URL, which is replaced by `to`
+    Prefix,
+    /// A glob pattern, where the whole URL is replaced by `to`
+    Glob,
+    /// A regular expression, where `to` can use its captures, like `$1`
+    Regex,
+    /// The URL's host, keeping its path and query
+    Host,
+}
+
+/// A compiled `from` of a rule, which can also be used to only find URLs.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Exact(String),
+    Prefix(String),
+    Glob(glob::Pattern),
+    Regex(regex::Regex),
+    Host(String),
+}
+impl Pattern {
+    pub fn new(kind: Match, from: &str) -> Result<Self, RewriteErr> {
+        Ok(match kind {
+            Match::Exact => Pattern::Exact(String::from(from)),
+            Match::Prefix => Pattern::Prefix(String::from(from)),
+            Match::Glob => Pattern::Glob(glob::Pattern::new(from).map_err(RewriteErr::Glob)?),
+            Match::Regex => Pattern::Regex(regex::Regex::new(from).map_err(RewriteErr::Regex)?),
+            Match::Host => Pattern::Host(from.to_lowercase()),
+        })
+    }
+
+    pub fn matches(&self, url: &str) -> bool {
+        match self {
+            Pattern::Exact(e) => url == e,
+            Pattern::Prefix(p) => url.starts_with(p.as_str()),
+            Pattern::Glob(p) => p.matches(url),
+            Pattern::Regex(r) => r.is_match(url),
+            Pattern::Host(h) => host(url).is_some_and(|r| url[r].to_lowercase() == *h),
+        }
+    }
+
+    /// `url` with the matched part replaced by `to`, if it matches.
+    fn replace(&self, url: &str, to: &str) -> Option<String> {
+        match self {
+            Pattern::Exact(e) => (url == e).then(|| String::from(to)),
+            Pattern::Prefix(p) => url.strip_prefix(p.as_str()).map(|r| format!("{}{}", to, r)),
+            Pattern::Glob(p) => p.matches(url).then(|| String::from(to)),
+            Pattern::Regex(r) => r.is_match(url).then(|| r.replace(url, to).into_owned()),
+            Pattern::Host(h) => {
+                let host = host(url)?;
+                (url[host.clone()].to_lowercase() == *h)
+                    .then(|| format!("{}{}{}", &url[..host.start], to, &url[host.end..]))
+            }
+        }
+    }
+}
+
+/// A set of rewriting rules. Exact rules are looked up directly, so many of
+/// them can be applied at once, the other rules are tried in the order they
+/// were added.
+///
+/// A URL is rewritten once, by the first rule that matches it, and the result
+/// isn't matched again. Rules `a → b` and `b → c` rewrite `a` to `b`, not to
+/// `c` as replacing each pair in turn used to.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    exact: HashMap<String, String>,
+    patterns: Vec<(Pattern, String)>,
+}
+impl Rules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, kind: Match, from: &str, to: &str) -> Result<(), RewriteErr> {
+        if kind == Match::Exact {
+            self.exact.insert(String::from(from), String::from(to));
+        } else {
+            self.patterns
+                .push((Pattern::new(kind, from)?, String::from(to)));
+        }
+        Ok(())
+    }
+
+    /// Reads rules from a YAML or JSON mapping of `from` to `to`. Keys are
+    /// exact URLs, unless prefixed by the kind of match:
+    ///
+    /// ```yaml
+    /// https://guz.one/old: https://guz.one/new
+    /// "prefix:https://guz.one/blog/": https://guz.one/posts/
+    /// "host:guz.one": guz.tumblr.com
+    /// "glob:https://*.example.com/**": https://example.com
+    /// "regex:^https://guz.one/(\d+)$": https://guz.tumblr.com/post/$1
+    /// ```
+    pub fn load(path: &Path) -> Result<Self, RewriteErr> {
+        let file = std::fs::read_to_string(path).map_err(|err| RewriteErr::Io {
+            path: path.to_path_buf(),
+            err,
+        })?;
+        Self::parse(&file)
+    }
+
+    pub fn parse(string: &str) -> Result<Self, RewriteErr> {
+        let mapping: yaml::Mapping = yaml::from_str(string).map_err(RewriteErr::Parsing)?;
+        let mut rules = Self::new();
+        for (from, to) in mapping {
+            let (Some(from), Some(to)) = (from.as_str(), to.as_str()) else {
+                return Err(RewriteErr::InvalidRule(from));
+            };
+            let (kind, from) = [
+                ("prefix:", Match::Prefix),
+                ("glob:", Match::Glob),
+                ("regex:", Match::Regex),
+                ("host:", Match::Host),
+            ]
+            .into_iter()
+            .find_map(|(p, kind)| Some((kind, from.strip_prefix(p)?)))
+            .unwrap_or((Match::Exact, from));
+            rules.push(kind, from, to)?;
+        }
+        Ok(rules)
+    }
+
+    /// The rewritten URL, from the first rule that matches it.
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        if let Some(to) = self.exact.get(url) {
+            return Some(to.clone());
+        }
+        self.patterns
+            .iter()
+            .find_map(|(pattern, to)| pattern.replace(url, to))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.patterns.is_empty()
+    }
+}
+
+/// Range of the host in the URL, found in the text so the rest of the URL is
+/// kept as written.
+/// IPv6 hosts keep their brackets, like `[::1]`.
+fn host(url: &str) -> Option<std::ops::Range<usize>> {
+    let start = url.find("://")? + 3;
+    let authority = &url[start..];
+    let end = authority.find(['/', '?', '#']).unwrap_or(authority.len());
+    let authority = &authority[..end];
+    let host_start = authority.rfind('@').map(|i| i + 1).unwrap_or(0);
+    let host = &authority[host_start..];
+    let host_end = if host.starts_with('[') {
+        host.find(']')? + 1
+    } else {
+        host.find(':').unwrap_or(host.len())
+    };
+    Some(start + host_start..start + host_start + host_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Match, Rules};
+
+    #[test]
+    fn rewrite_rules() {
+        let mut rules = Rules::parse(
+            "https://guz.one/old: https://guz.one/new\n\
+            \"prefix:https://guz.one/blog/\": https://guz.one/posts/\n\
+            \"regex:^https://guz.one/(\\\\d+)$\": https://guz.tumblr.com/post/$1\n\
+            \"host:guz.one\": guz.tumblr.com\n",
+        )
+        .unwrap();
+        rules
+            .push(
+                Match::Glob,
+                "https://*.example.com/**",
+                "https://example.com",
+            )
+            .unwrap();
+
+        let rewrite = |url| rules.rewrite(url);
+        assert_eq!(
+            rewrite("https://guz.one/old"),
+            Some("https://guz.one/new".into())
+        );
+        assert_eq!(
+            rewrite("https://guz.one/blog/a?b=c"),
+            Some("https://guz.one/posts/a?b=c".into())
+        );
+        assert_eq!(
+            rewrite("https://guz.one/123"),
+            Some("https://guz.tumblr.com/post/123".into())
+        );
+        assert_eq!(
+            rewrite("https://guz.one/about?lang=en#me"),
+            Some("https://guz.tumblr.com/about?lang=en#me".into())
+        );
+        assert_eq!(
+            rewrite("https://www.example.com/a/b"),
+            Some("https://example.com".into())
+        );
+        assert_eq!(
+            rewrite("http://user@GUZ.one:8080"),
+            Some("http://user@guz.tumblr.com:8080".into())
+        );
+        assert_eq!(rewrite("https://other.com/"), None);
+
+        let mut rules = Rules::new();
+        rules.push(Match::Host, "[::1]", "localhost").unwrap();
+        rules.push(Match::Exact, "a", "b").unwrap();
+        rules.push(Match::Exact, "b", "c").unwrap();
+        assert_eq!(
+            rules.rewrite("http://[::1]:8080/a"),
+            Some("http://localhost:8080/a".into())
+        );
+        assert_eq!(rules.rewrite("http://[::2]:8080/"), None);
+        assert_eq!(rules.rewrite("a"), Some("b".into()));
+    }
+}
@@ -1,16 +1,20 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::{ArgAction, Parser, Subcommand};
 use clio::Input;
 
-use comrak::arena_tree::Node;
-use comrak::nodes::{Ast, LineColumn, NodeValue};
+use comrak::nodes::AstNode;
+use dot013_mdparser::config::Config;
 use dot013_mdparser::convert;
-use dot013_mdparser::frontmatter::Frontmatter;
-use dot013_mdparser::links;
+use dot013_mdparser::files;
+use dot013_mdparser::frontmatter::format::Format;
+use dot013_mdparser::frontmatter::schema::Schema;
+use dot013_mdparser::frontmatter::{self, Frontmatter};
+use dot013_mdparser::frontmatter::{compute, merge, query};
+use dot013_mdparser::links::{self, rewrite};
+use dot013_mdparser::splice;
+use dot013_mdparser::utils;
 
 #[derive(Parser, Debug)]
 #[command(version = "0.1", about = "", long_about = None, propagate_version = true)]
@@ -18,12 +22,47 @@ struct Cli {
     #[command(subcommand)]
     command: Commands,
 
+    /// Files, directories or glob patterns to process, "-" reads from stdin
     #[arg(global = true, default_value = "-", last = true)]
-    input: Input,
+    inputs: Vec<String>,
+
+    /// Walk directories recursively
+    #[arg(short, long, global = true, action = ArgAction::SetTrue)]
+    recursive: bool,
+
+    /// Only process files in directories and globs matching these patterns
+    #[arg(long, global = true)]
+    include: Vec<String>,
+
+    /// Skip files in directories and globs matching these patterns
+    #[arg(long, global = true)]
+    exclude: Vec<String>,
 
     #[arg(short, long, global = true, action = ArgAction::SetTrue)]
     write: bool,
 
+    /// When writing files in place, keep their previous contents in a .bak file
+    #[arg(long, global = true, action = ArgAction::SetTrue)]
+    backup: bool,
+
+    /// Configuration file to use, instead of the .mdparser.toml found in the
+    /// file's directory or its parents
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Only change the edited links, images and frontmatter in the source,
+    /// instead of re-rendering the whole document
+    #[arg(long, global = true, action = ArgAction::SetTrue)]
+    minimal_edits: bool,
+
+    /// Print a unified diff of the changes instead of the resulting document
+    #[arg(long, global = true, action = ArgAction::SetTrue)]
+    diff: bool,
+
+    /// Exit with an error if any file would be changed, without printing it
+    #[arg(long, global = true, action = ArgAction::SetTrue)]
+    check: bool,
+
     #[arg(long, global = true, default_value = "lines")]
     list_format: cli::ListFormat,
 
@@ -34,6 +73,8 @@ struct Cli {
 #[derive(Debug, Subcommand)]
 enum FrontmatterCommands {
     Set {
+        /// Key or path to a nested value, like author.name, tags[0] or
+        /// links[-1].url, creating the values before it
         #[clap()]
         property: String,
 
@@ -41,49 +82,225 @@ enum FrontmatterCommands {
         value: String,
     },
     Remove {
+        /// Key or path to a nested value
+        #[clap()]
+        property: String,
+    },
+    /// Add values, parsed as YAML, to the end of the list at a key or path,
+    /// creating the list if it's missing
+    Append {
+        #[clap()]
+        property: String,
+
+        #[clap(required = true)]
+        values: Vec<String>,
+    },
+    /// Add values to the list at a key or path, skipping the ones already in
+    /// it
+    AddUnique {
+        #[clap()]
+        property: String,
+
+        #[clap(required = true)]
+        values: Vec<String>,
+    },
+    /// Remove every item equal to one of the values from the list at a key or
+    /// path
+    RemoveItem {
+        #[clap()]
+        property: String,
+
+        #[clap(required = true)]
+        values: Vec<String>,
+    },
+    /// Flip the boolean at a key or path, setting it to true if it's missing
+    Toggle {
         #[clap()]
         property: String,
     },
     Get {
+        /// Key or path to a nested value
         #[clap()]
         property: String,
 
         #[arg(short = 'j', long, action = ArgAction::SetTrue)]
         to_json: bool,
     },
+    /// Insert a YAML mapping into the frontmatter
     Insert {
-        #[clap()]
-        value: String,
+        #[clap(required_unless_present = "from")]
+        value: Option<String>,
+
+        /// Read the mapping from a YAML, TOML or JSON file, told apart by its
+        /// extension, or from stdin with "-" when the documents are files
+        #[arg(long, conflicts_with = "value")]
+        from: Option<PathBuf>,
+
+        /// How the mapping is combined with the existing values
+        #[arg(long, value_enum, default_value = "replace")]
+        strategy: merge::Strategy,
     },
     Extract {
         #[arg(short = 'j', long, action = ArgAction::SetTrue)]
         to_json: bool,
     },
+    /// Report the keys that don't follow a schema, exiting with an error if
+    /// any is found
+    Validate {
+        /// YAML file mapping keys, or paths, to their type, if they're
+        /// required, a pattern, allowed values and a default
+        #[arg(long)]
+        schema: PathBuf,
+
+        /// Set the missing keys that have a default. The document is only
+        /// written when no other problem is left
+        #[arg(long)]
+        fill_defaults: bool,
+    },
+    /// Fill fields derived from the document: its title, word count, reading
+    /// time, last update, summary, image and number of links. Their keys are
+    /// set in the [compute] section of the configuration
+    Compute {
+        /// Only compute these fields
+        #[arg(long, value_enum, value_delimiter = ',')]
+        only: Vec<compute::Field>,
+
+        /// Replace the values already in the frontmatter
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// List the documents whose frontmatter matches an expression, like
+    /// 'state == "draft" && tags contains "art" && date < today+7'
+    Query {
+        #[clap()]
+        expression: String,
+
+        /// Keys or paths to write for each document, after its path
+        #[arg(long, value_delimiter = ',')]
+        fields: Vec<String>,
+
+        #[arg(long, value_enum, default_value = "lines")]
+        format: query::Output,
+    },
+    /// Rewrites the frontmatter in another format, JSON being written as a
+    /// bare object
+    Convert {
+        #[arg(long)]
+        to: frontmatter::format::Format,
+    },
 }
 
 #[derive(Debug, Subcommand)]
 enum LinksCommands {
-    List {},
+    List {
+        /// Only list URL links or wikilinks
+        #[arg(long)]
+        kind: Option<cli::LinkKind>,
+    },
     Replace {
-        #[clap(num_args = 2, value_names = ["FROM", "TO"], required = true)]
+        /// Pairs of FROM and TO. Each URL is only rewritten by the first rule
+        /// that matches it, so pairs don't chain: "a b b c" rewrites a to b
+        #[clap(num_args = 2, value_names = ["FROM", "TO"], required_unless_present = "rules")]
         replace: Vec<String>,
+
+        /// How FROM is matched against the URLs
+        #[arg(long = "match", value_enum, default_value = "exact")]
+        match_kind: rewrite::Match,
+
+        /// YAML or JSON file mapping FROM to TO, with keys optionally prefixed
+        /// by "prefix:", "glob:", "regex:" or "host:"
+        #[arg(long)]
+        rules: Option<PathBuf>,
     },
     Remove {
-        #[clap(num_args = 1, value_names = ["URL"], required = true)]
+        #[clap(num_args = 1, value_names = ["URL"], required_unless_present = "all_external")]
         links: Vec<String>,
+
+        /// How URL is matched against the links
+        #[arg(long = "match", value_enum, default_value = "exact")]
+        match_kind: rewrite::Match,
+
+        /// Keep the text of the removed links, or delete it with them
+        #[arg(long, value_enum, default_value = "unlink")]
+        mode: links::Removal,
+
+        /// Remove every link to another site than the configured base_url
+        #[arg(long, action = ArgAction::SetTrue)]
+        all_external: bool,
     },
+    /// Canonicalize the URLs of links and images, removing tracking
+    /// parameters and unwrapping redirects, following the [normalize] rules of
+    /// the configuration. Redirects that can't be unwrapped are reported
+    /// instead, exiting with an error
+    Normalize {},
+    /// Report relative links and images to missing files or headings, exiting
+    /// with an error if any is found
+    Check {},
+    /// Turn inline links and images into reference ones, sharing a definition
+    /// for each URL
+    ToReference {},
+    /// Turn reference links and images into inline ones, removing their
+    /// definitions
+    ToInline {},
 }
 
 #[derive(Debug, Subcommand)]
 enum ImagesCommands {
     List {},
+    /// Report images with missing, file name or too long alt text, and
+    /// decorative images without the "decorative" title, exiting with an
+    /// error if any is found
+    Audit {
+        /// Longest alt text allowed, Tumblr's limit by default
+        #[arg(long, default_value_t = links::audit::MAX_ALT)]
+        max_alt: usize,
+    },
+    /// Set the alt text of the images with the URL
+    SetAlt {
+        #[clap()]
+        url: String,
+
+        #[clap()]
+        text: String,
+    },
+    /// Set the title of the images with the URL
+    SetTitle {
+        #[clap()]
+        url: String,
+
+        #[clap()]
+        title: String,
+    },
     Replace {
-        #[clap(num_args = 2, value_names = ["FROM", "TO"], required = true)]
+        /// Pairs of FROM and TO. Each URL is only rewritten by the first rule
+        /// that matches it, so pairs don't chain: "a b b c" rewrites a to b
+        #[clap(num_args = 2, value_names = ["FROM", "TO"], required_unless_present = "rules")]
         replace: Vec<String>,
+
+        /// How FROM is matched against the URLs
+        #[arg(long = "match", value_enum, default_value = "exact")]
+        match_kind: rewrite::Match,
+
+        /// YAML or JSON file mapping FROM to TO, with keys optionally prefixed
+        /// by "prefix:", "glob:", "regex:" or "host:"
+        #[arg(long)]
+        rules: Option<PathBuf>,
     },
     Remove {
-        #[clap(num_args = 1, value_names = ["URL"], required = true)]
+        #[clap(num_args = 1, value_names = ["URL"], required_unless_present = "all_external")]
         images: Vec<String>,
+
+        /// How URL is matched against the images
+        #[arg(long = "match", value_enum, default_value = "exact")]
+        match_kind: rewrite::Match,
+
+        /// Keep the alt text of the removed images, or delete it with them
+        #[arg(long, value_enum, default_value = "unlink")]
+        mode: links::Removal,
+
+        /// Remove every image from another site than the configured base_url
+        #[arg(long, action = ArgAction::SetTrue)]
+        all_external: bool,
     },
 }
 
@@ -104,84 +321,597 @@ enum Commands {
     Convert {
         #[arg(short, long)]
         format: convert::Formats,
+
+        /// Read the input as a Tumblr NPF post (e.g. from the API) instead of Markdown
+        #[arg(long, action = ArgAction::SetTrue)]
+        from_npf: bool,
+
+        /// Canonicalize the URLs of links and images, with the [normalize]
+        /// rules of the configuration
+        #[arg(long, action = ArgAction::SetTrue)]
+        normalize_links: bool,
+    },
+    /// Rewrite relative links and images so they still resolve after the
+    /// document, or the files it links to, are moved
+    Rebase {
+        /// Where the document was, the input file by default
+        #[arg(long)]
+        from: Option<PathBuf>,
+
+        /// Where the document is moved to, or its new directory if it ends
+        /// with a slash
+        #[arg(long, required_unless_present = "asset_root")]
+        to: Option<PathBuf>,
+
+        /// Directory the linked files moved from, and the one they moved to
+        #[arg(long, num_args = 2, value_names = ["OLD", "NEW"])]
+        asset_root: Vec<PathBuf>,
+    },
+    /// Show the post converted to Tumblr NPF, styled for the terminal
+    Preview {
+        /// Read the input as a Tumblr NPF post (e.g. from the API) instead of Markdown
+        #[arg(long, action = ArgAction::SetTrue)]
+        from_npf: bool,
+
+        /// Canonicalize the URLs of links and images, with the [normalize]
+        /// rules of the configuration
+        #[arg(long, action = ArgAction::SetTrue)]
+        normalize_links: bool,
     },
 }
 
 fn main() {
     let mut cli = Cli::parse();
 
-    let file = match std::io::read_to_string(&mut cli.input) {
-        Ok(s) => s,
+    let paths = match files::Filter::new(&cli.include, &cli.exclude)
+        .and_then(|filter| files::discover(&cli.inputs, cli.recursive, &filter))
+    {
+        Ok(p) => p,
         Err(e) => {
             cli::print_error(
                 cli::Error {
                     code: cli::ErrorCode::EIORD,
-                    description: format!("Failed to read input\n{e:#?}"),
+                    description: format!("Failed to find input files\n{e:#?}"),
+                    fix: None,
+                    url: None,
+                },
+                cli.surpress_errors,
+            );
+            return;
+        }
+    };
+    let many = paths.len() > 1;
+
+    if let cli::ListFormat::JSON = &cli.list_format {
+        if paths.len() == 1 && paths[0].to_str() == Some("-") && Input::std().is_tty() {
+            cli.list_format = cli::ListFormat::PrettyJSON
+        }
+    }
+
+    let config = match cli.config.as_deref().map(Config::load).transpose() {
+        Ok(c) => c,
+        Err(e) => {
+            cli::print_error(cli::config_error(e), cli.surpress_errors);
+            return;
+        }
+    };
+
+    let matchers = match rules(&cli.command).and_then(|rules| Ok((rules, patterns(&cli.command)?)))
+    {
+        Ok((rules, remove)) => Matchers { rules, remove },
+        Err(e) => {
+            cli::print_error(
+                cli::Error {
+                    code: cli::ErrorCode::EPRSG,
+                    description: format!("Failed to read the URL patterns\n{e:#?}"),
+                    fix: None,
+                    url: None,
+                },
+                cli.surpress_errors,
+            );
+            return;
+        }
+    };
+
+    let query = match &cli.command {
+        Commands::Frontmatter {
+            command:
+                FrontmatterCommands::Query {
+                    expression,
+                    fields,
+                    format,
+                },
+        } => match cli::query(expression, fields) {
+            Ok((_, fields)) => Some((fields, *format)),
+            Err(e) => {
+                cli::print_error(e, cli.surpress_errors);
+                return;
+            }
+        },
+        _ => None,
+    };
+    let mut matches = vec![];
+
+    if let Commands::Frontmatter {
+        command:
+            FrontmatterCommands::Insert {
+                value,
+                from: Some(from),
+                ..
+            },
+    } = &mut cli.command
+    {
+        if from == Path::new("-") && paths.iter().any(|p| p == Path::new("-")) {
+            cli::print_error(
+                cli::Error {
+                    code: cli::ErrorCode::EIORD,
+                    description: String::from(
+                        "Both the inserted values and the document are read from stdin",
+                    ),
                     fix: Some(String::from(
-                        "Try to check if you have input permission to input file",
+                        "Pass the documents as files, after --, or the values with --from FILE",
                     )),
                     url: None,
                 },
                 cli.surpress_errors,
             );
-            return ();
+            return;
+        }
+        match Input::new(&*from).and_then(|mut i| Ok(std::io::read_to_string(&mut i)?)) {
+            Ok(s) => *value = Some(s),
+            Err(e) => {
+                cli::print_error(
+                    cli::Error {
+                        code: cli::ErrorCode::EIORD,
+                        description: format!("Failed to read {}\n{e:#?}", from.display()),
+                        fix: None,
+                        url: None,
+                    },
+                    cli.surpress_errors,
+                );
+                return;
+            }
+        }
+    }
+
+    let mut grouped = serde_json::Map::new();
+    let mut changed = false;
+    let mut problems = false;
+
+    for path in paths {
+        let mut input = match Input::new(&path) {
+            Ok(i) => i,
+            Err(e) => {
+                cli::print_error(
+                    cli::Error {
+                        code: cli::ErrorCode::EIORD,
+                        description: format!("Failed to open input {}\n{e:#?}", path.display()),
+                        fix: None,
+                        url: None,
+                    },
+                    cli.surpress_errors,
+                );
+                continue;
+            }
+        };
+        let name = path.display().to_string();
+
+        let file = match std::io::read_to_string(&mut input) {
+            Ok(s) => s,
+            Err(e) => {
+                cli::print_error(
+                    cli::Error {
+                        code: cli::ErrorCode::EIORD,
+                        description: format!("Failed to read input {name}\n{e:#?}"),
+                        fix: Some(String::from(
+                            "Try to check if you have input permission to input file",
+                        )),
+                        url: None,
+                    },
+                    cli.surpress_errors,
+                );
+                continue;
+            }
+        };
+
+        let dir = input_dir(&input);
+        let mut config = match &config {
+            Some(c) => c.clone(),
+            None => match Config::discover(dir) {
+                Ok(c) => c,
+                Err(e) => {
+                    cli::print_error(cli::config_error(e), cli.surpress_errors);
+                    continue;
+                }
+            },
+        };
+        // Without a directory in the configuration, wikilinks' target files
+        // are looked up next to the file
+        config
+            .npf
+            .wikilinks
+            .directory
+            .get_or_insert_with(|| dir.to_path_buf());
+        if let Commands::Convert {
+            normalize_links: true,
+            ..
+        }
+        | Commands::Preview {
+            normalize_links: true,
+            ..
+        } = &cli.command
+        {
+            config.npf.normalize = Some(config.normalize.clone());
+        }
+        let options = config.options();
+
+        let arena = comrak::Arena::new();
+        let ast = utils::parse_document(&arena, &file, &options);
+
+        let snapshot = cli.minimal_edits.then(|| splice::snapshot(ast));
+        let references = links::references::snapshot(ast, &file);
+        let result = run(&cli, &config, &matchers, &input, &file, &arena, ast);
+
+        let is_markdown = matches!(
+            result,
+            cli::ResultType::Markdown(_) | cli::ResultType::Source(_)
+        );
+        let result = match (result, snapshot) {
+            (cli::ResultType::Markdown(_), Some(snapshot)) => match snapshot.splice(&file) {
+                Ok(s) => cli::ResultType::Source(s),
+                Err(e) => cli::ResultType::Err(cli::Error {
+                    code: cli::ErrorCode::EPRSG,
+                    description: format!("Failed to apply the changes to the source\n{e:#?}"),
+                    fix: Some(String::from(
+                        "Try again without --minimal-edits, re-rendering the whole document",
+                    )),
+                    url: None,
+                }),
+            },
+            (cli::ResultType::Markdown(ast), None) => {
+                references.restore(&arena);
+                utils::literal_wikilinks(ast);
+                cli::ResultType::Markdown(ast)
+            }
+            (result, _) => result,
+        };
+        if let cli::ResultType::List(list) = &result {
+            problems |= list.iter().any(|e| {
+                matches!(
+                    e,
+                    cli::Entry::Broken(_)
+                        | cli::Entry::Issue(_)
+                        | cli::Entry::Violation(_)
+                        | cli::Entry::Failure(_)
+                )
+            });
+        }
+        let str = match result {
+            cli::ResultType::List(list) if query.is_some() => {
+                matches.extend(list.into_iter().filter_map(|e| match e {
+                    cli::Entry::Match(m) => Some(m),
+                    _ => None,
+                }));
+                continue;
+            }
+            cli::ResultType::Err(mut e) => {
+                if many {
+                    e.description = format!("In {}:\n{}", name, e.description);
+                }
+                cli::print_error(e, cli.surpress_errors);
+                continue;
+            }
+            cli::ResultType::List(list) if many => match cli.list_format {
+                cli::ListFormat::Lines | cli::ListFormat::Comma => {
+                    cli::prefixed_list(&name, &list, &cli.list_format)
+                }
+                _ => {
+                    match serde_json::to_value(&list) {
+                        Ok(v) => {
+                            grouped.insert(name, v);
+                        }
+                        Err(e) => cli::print_error(
+                            cli::Error {
+                                code: cli::ErrorCode::EPRSG,
+                                description: format!(
+                                    "Failed to parse list into a JSON output\n{e:#?}"
+                                ),
+                                fix: None,
+                                url: None,
+                            },
+                            cli.surpress_errors,
+                        ),
+                    }
+                    continue;
+                }
+            },
+            result => match cli::result_to_str(result, &cli.list_format, &options) {
+                Ok(s) if many && !(is_markdown && (cli.write || cli.diff || cli.check)) => {
+                    format!("==> {} <==\n{}\n", name, s.trim_end())
+                }
+                Ok(s) => s,
+                Err(e) => {
+                    cli::print_error(e, cli.surpress_errors);
+                    continue;
+                }
+            },
+        };
+
+        if is_markdown && (cli.diff || cli.check) {
+            if str != file {
+                if cli.check {
+                    eprintln!("{} would be changed", name);
+                    changed = true;
+                }
+                if cli.diff {
+                    print!("{}", files::diff(&name, &file, &str));
+                }
+            }
+            if !cli.write || input.is_std() {
+                continue;
+            }
+        }
+
+        if input.is_std() || !cli.write || !is_markdown {
+            if let Err(e) = std::io::stdout().write(str.as_bytes()) {
+                cli::print_error(
+                    cli::Error {
+                        code: cli::ErrorCode::EIOWR,
+                        description: format!("Error trying to write result to stdout\n{e:#?}"),
+                        fix: None,
+                        url: None,
+                    },
+                    cli.surpress_errors,
+                );
+            }
+        } else if let Some(f) = input.path().to_str() {
+            if let Err(e) = files::write(&PathBuf::from(f), &str, cli.backup) {
+                cli::print_error(
+                    cli::Error {
+                        code: cli::ErrorCode::EIOWR,
+                        description: format!("Error, failed to write to file {name}\n{e:#?}"),
+                        fix: None,
+                        url: None,
+                    },
+                    cli.surpress_errors,
+                );
+            }
+        } else {
+            cli::print_error(
+                cli::Error {
+                    code: cli::ErrorCode::EIOTY,
+                    description: String::from("Error, output is not a valid file"),
+                    fix: None,
+                    url: None,
+                },
+                cli.surpress_errors,
+            );
+        }
+    }
+
+    if !grouped.is_empty() {
+        let json = if let cli::ListFormat::PrettyJSON = cli.list_format {
+            serde_json::to_string_pretty(&grouped)
+        } else {
+            serde_json::to_string(&grouped)
+        };
+        match json {
+            Ok(s) => println!("{}", s),
+            Err(e) => cli::print_error(
+                cli::Error {
+                    code: cli::ErrorCode::EPRSG,
+                    description: format!("Failed to parse lists into a JSON output\n{e:#?}"),
+                    fix: None,
+                    url: None,
+                },
+                cli.surpress_errors,
+            ),
+        }
+    }
+
+    if let Some((fields, format)) = query {
+        match query::write(&matches, &fields, format) {
+            Ok(s) => print!("{}", s),
+            Err(e) => cli::print_error(
+                cli::Error {
+                    code: cli::ErrorCode::EPRSG,
+                    description: format!("Failed to write the query matches\n{e:#?}"),
+                    fix: None,
+                    url: None,
+                },
+                cli.surpress_errors,
+            ),
+        }
+    }
+
+    if changed || problems {
+        std::process::exit(1);
+    }
+}
+
+/// Directory relative paths of the input are resolved from.
+fn input_dir(input: &Input) -> &Path {
+    match input.path().parent() {
+        Some(d) if !input.is_std() && d != Path::new("") => d,
+        _ => Path::new("."),
+    }
+}
+
+/// Directory of a document's path, or the path itself if it is a directory.
+fn document_dir(path: &Path) -> PathBuf {
+    if path.is_dir() || path.to_string_lossy().ends_with('/') {
+        path.to_path_buf()
+    } else {
+        path.parent().unwrap_or(Path::new(".")).to_path_buf()
+    }
+}
+
+/// URL patterns from the command's arguments, compiled once for every file.
+struct Matchers {
+    rules: rewrite::Rules,
+    remove: Vec<rewrite::Pattern>,
+}
+
+/// Rewriting rules of the replace commands, from their arguments and rules
+/// file.
+fn rules(command: &Commands) -> Result<rewrite::Rules, rewrite::RewriteErr> {
+    let (replace, match_kind, file) = match command {
+        Commands::Links {
+            command:
+                LinksCommands::Replace {
+                    replace,
+                    match_kind,
+                    rules,
+                },
         }
+        | Commands::Images {
+            command:
+                ImagesCommands::Replace {
+                    replace,
+                    match_kind,
+                    rules,
+                },
+        } => (replace, *match_kind, rules),
+        _ => return Ok(rewrite::Rules::new()),
     };
 
-    let arena = comrak::Arena::new();
-    let ast = comrak::parse_document(&arena, &file, &dot013_mdparser::utils::default_options());
+    let mut rules = match file {
+        Some(f) => rewrite::Rules::load(f)?,
+        None => rewrite::Rules::new(),
+    };
+    for pair in replace.chunks(2) {
+        rules.push(match_kind, &pair[0], &pair[1])?;
+    }
+    Ok(rules)
+}
+
+/// Patterns of the URLs given to the remove commands.
+fn patterns(command: &Commands) -> Result<Vec<rewrite::Pattern>, rewrite::RewriteErr> {
+    let (urls, match_kind) = match command {
+        Commands::Links {
+            command: LinksCommands::Remove {
+                links, match_kind, ..
+            },
+        } => (links, *match_kind),
+        Commands::Images {
+            command: ImagesCommands::Remove {
+                images, match_kind, ..
+            },
+        } => (images, *match_kind),
+        _ => return Ok(vec![]),
+    };
+    urls.iter()
+        .map(|u| rewrite::Pattern::new(match_kind, u))
+        .collect()
+}
 
-    let result = match &cli.command {
+fn run<'a>(
+    cli: &Cli,
+    config: &Config,
+    matchers: &Matchers,
+    input: &Input,
+    file: &str,
+    arena: &'a comrak::Arena<AstNode<'a>>,
+    ast: &'a AstNode<'a>,
+) -> cli::ResultType<'a, cli::Entry> {
+    match &cli.command {
         Commands::Links { command } => match command {
-            LinksCommands::List {} => cli::ResultType::List(links::get_links(ast)),
-            LinksCommands::Replace { replace } => {
-                replace
-                    .chunks(2)
-                    .for_each(|p| links::replace_links(ast, &p[0], &p[1]));
+            LinksCommands::List { kind } => cli::ResultType::List(
+                links::list_links(ast, file)
+                    .into_iter()
+                    .filter(|l| match kind {
+                        Some(cli::LinkKind::Url) => l.kind != links::LinkKind::Wikilink,
+                        Some(cli::LinkKind::Wikilink) => l.kind == links::LinkKind::Wikilink,
+                        None => true,
+                    })
+                    .map(cli::Entry::Link)
+                    .collect(),
+            ),
+            LinksCommands::Replace { .. } => {
+                links::rewrite_links(ast, &matchers.rules);
+                links::rewrite_wikilinks(ast, &matchers.rules);
                 cli::ResultType::Markdown(ast)
             }
-            LinksCommands::Remove { links } => {
-                links.iter().for_each(|l| links::remove_link(ast, l));
+            LinksCommands::Remove {
+                mode, all_external, ..
+            } => {
+                let remove = |url: &str| {
+                    matchers.remove.iter().any(|p| p.matches(url))
+                        || (*all_external && links::is_external(url, config.npf.base_url.as_ref()))
+                };
+                links::remove_links_where(ast, *mode, remove);
+                links::remove_wikilinks_where(ast, *mode, remove);
                 cli::ResultType::Markdown(ast)
             }
+            LinksCommands::Normalize {} => {
+                let failures = links::normalize_failures(ast, &config.normalize);
+                if failures.is_empty() {
+                    links::normalize_links(ast, &config.normalize);
+                    links::normalize_images(ast, &config.normalize);
+                    cli::ResultType::Markdown(ast)
+                } else {
+                    cli::ResultType::List(failures.into_iter().map(cli::Entry::Failure).collect())
+                }
+            }
+            LinksCommands::Check {} => cli::ResultType::List(
+                links::check::check(ast, file, input_dir(input), &config.options())
+                    .into_iter()
+                    .map(cli::Entry::Broken)
+                    .collect(),
+            ),
+            LinksCommands::ToReference {} => {
+                cli::source_result(links::references::to_reference(ast, file))
+            }
+            LinksCommands::ToInline {} => {
+                cli::source_result(links::references::to_inline(ast, file))
+            }
         },
         Commands::Images { command } => match command {
-            ImagesCommands::List {} => cli::ResultType::List(links::get_images(ast)),
-            ImagesCommands::Replace { replace } => {
-                replace
-                    .chunks(2)
-                    .for_each(|p| links::replace_images(ast, &p[0], &p[1]));
+            ImagesCommands::List {} => cli::ResultType::List(
+                links::list_images(ast, file)
+                    .into_iter()
+                    .map(cli::Entry::Image)
+                    .collect(),
+            ),
+            ImagesCommands::Audit { max_alt } => cli::ResultType::List(
+                links::audit::audit_images(ast, file, *max_alt)
+                    .into_iter()
+                    .map(cli::Entry::Issue)
+                    .collect(),
+            ),
+            ImagesCommands::SetAlt { url, text } => {
+                links::set_image_alt(arena, ast, url, text);
+                cli::ResultType::Markdown(ast)
+            }
+            ImagesCommands::SetTitle { url, title } => {
+                links::set_image_title(ast, url, title);
+                cli::ResultType::Markdown(ast)
+            }
+            ImagesCommands::Replace { .. } => {
+                links::rewrite_images(ast, &matchers.rules);
                 cli::ResultType::Markdown(ast)
             }
-            ImagesCommands::Remove { images } => {
-                images.iter().for_each(|l| links::remove_image(ast, l));
+            ImagesCommands::Remove {
+                mode, all_external, ..
+            } => {
+                links::remove_images_where(ast, *mode, |url| {
+                    matchers.remove.iter().any(|p| p.matches(url))
+                        || (*all_external && links::is_external(url, config.npf.base_url.as_ref()))
+                });
                 cli::ResultType::Markdown(ast)
             }
         },
         Commands::Frontmatter { command } => {
-            if let None = ast.children().find(|c| {
-                if let NodeValue::FrontMatter(_) = c.data.borrow().value {
-                    true
-                } else {
-                    false
-                }
-            }) {
-                let node = arena.alloc(Node::new(RefCell::from(Ast::new(
-                    NodeValue::FrontMatter(String::from("---\n\n---")),
-                    LineColumn { line: 0, column: 0 },
-                ))));
-                ast.prepend(node);
-            }
+            frontmatter::prepend_empty(arena, ast);
 
             match Frontmatter::try_from(ast) {
                 Ok(mut frontmatter) => match command {
                     FrontmatterCommands::Set { property, value } => {
-                        match serde_yaml::from_str(&value) {
-                            Ok(value) => {
-                                frontmatter.insert(String::from(property), value);
-                                frontmatter.insert_ast(ast);
-                                cli::ResultType::Markdown(ast)
-                            }
+                        match serde_yaml::from_str(value) {
+                            Ok(value) => match frontmatter.set_path(property, value) {
+                                Ok(()) => cli::insert_frontmatter(&frontmatter, ast),
+                                Err(err) => cli::ResultType::Err(cli::path_error(property, err)),
+                            },
                             Err(err) => cli::ResultType::Err(cli::Error {
                                 code: cli::ErrorCode::EPRSG,
                                 description: format!(
@@ -194,14 +924,46 @@ fn main() {
                         }
                     }
                     FrontmatterCommands::Remove { property } => {
-                        let _ = frontmatter.remove(String::from(property));
-                        frontmatter.insert_ast(ast);
-                        cli::ResultType::Markdown(ast)
+                        match frontmatter.remove_path(property) {
+                            Ok(_) => cli::insert_frontmatter(&frontmatter, ast),
+                            Err(err) => cli::ResultType::Err(cli::path_error(property, err)),
+                        }
+                    }
+                    FrontmatterCommands::Append { property, values }
+                    | FrontmatterCommands::AddUnique { property, values }
+                    | FrontmatterCommands::RemoveItem { property, values } => {
+                        let values = match cli::yaml_values(values) {
+                            Ok(v) => v,
+                            Err(err) => return cli::ResultType::Err(err),
+                        };
+                        let result = match command {
+                            FrontmatterCommands::RemoveItem { .. } => {
+                                frontmatter.remove_items_path(property, &values).map(|_| ())
+                            }
+                            c => frontmatter.append_path(
+                                property,
+                                values,
+                                matches!(c, FrontmatterCommands::AddUnique { .. }),
+                            ),
+                        };
+                        match result {
+                            Ok(()) => cli::insert_frontmatter(&frontmatter, ast),
+                            Err(err) => cli::ResultType::Err(cli::path_error(property, err)),
+                        }
+                    }
+                    FrontmatterCommands::Toggle { property } => {
+                        match frontmatter.toggle_path(property) {
+                            Ok(_) => cli::insert_frontmatter(&frontmatter, ast),
+                            Err(err) => cli::ResultType::Err(cli::path_error(property, err)),
+                        }
                     }
                     FrontmatterCommands::Get { property, to_json } => {
-                        let value = frontmatter
-                            .get(String::from(property))
-                            .unwrap_or(&serde_yaml::Value::Null);
+                        let value = match frontmatter.get_path(property) {
+                            Ok(v) => v.unwrap_or(&serde_yaml::Value::Null),
+                            Err(err) => {
+                                return cli::ResultType::Err(cli::path_error(property, err))
+                            }
+                        };
 
                         let result = if *to_json {
                             serde_json::to_string(value).map_err(|err| cli::Error {
@@ -230,24 +992,121 @@ fn main() {
                             Err(err) => cli::ResultType::Err(err),
                         }
                     }
-                    FrontmatterCommands::Insert { value } => {
-                        match serde_yaml::from_str::<HashMap<String, serde_yaml::Value>>(value) {
+                    FrontmatterCommands::Insert {
+                        value,
+                        from,
+                        strategy,
+                    } => {
+                        let format = from.as_deref().map_or(Format::Yaml, Format::from_path);
+                        match format.parse(value.as_deref().unwrap_or_default()) {
                             Ok(value) => {
-                                frontmatter.extend(value);
-                                frontmatter.insert_ast(ast);
-                                cli::ResultType::Markdown(ast)
+                                frontmatter.merge(value, *strategy);
+                                cli::insert_frontmatter(&frontmatter, ast)
                             }
                             Err(err) => cli::ResultType::Err(cli::Error {
                                 code: cli::ErrorCode::EPRSG,
                                 description: format!(
-                                    "Error parsing input value to yaml file:\n{:#?}",
-                                    err
+                                    "Error parsing input value to {:?} mapping:\n{:#?}",
+                                    format, err
                                 ),
                                 fix: None,
                                 url: None,
                             }),
                         }
                     }
+                    FrontmatterCommands::Validate {
+                        schema,
+                        fill_defaults,
+                    } => {
+                        let schema = match Schema::load(schema) {
+                            Ok(s) => s,
+                            Err(err) => {
+                                return cli::ResultType::Err(cli::Error {
+                                    code: cli::ErrorCode::EPRSG,
+                                    description: format!(
+                                        "Failed to read the frontmatter schema\n{:#?}",
+                                        err
+                                    ),
+                                    fix: None,
+                                    url: None,
+                                })
+                            }
+                        };
+                        if *fill_defaults {
+                            if let Err(err) = schema.fill_defaults(&mut frontmatter) {
+                                return cli::ResultType::Err(cli::Error {
+                                    code: cli::ErrorCode::EPRSG,
+                                    description: format!(
+                                        "Failed to set the default values\n{:#?}",
+                                        err
+                                    ),
+                                    fix: None,
+                                    url: None,
+                                });
+                            }
+                        }
+                        match schema.validate(&frontmatter) {
+                            v if v.is_empty() && *fill_defaults => {
+                                cli::insert_frontmatter(&frontmatter, ast)
+                            }
+                            v => cli::ResultType::List(
+                                v.into_iter().map(cli::Entry::Violation).collect(),
+                            ),
+                        }
+                    }
+                    FrontmatterCommands::Compute { only, overwrite } => {
+                        let modified = if input.is_std() {
+                            None
+                        } else {
+                            std::fs::metadata(input.path().path())
+                                .and_then(|m| m.modified())
+                                .ok()
+                        };
+                        let fields = if only.is_empty() {
+                            &compute::Field::ALL[..]
+                        } else {
+                            only
+                        };
+                        compute::apply(
+                            &mut frontmatter,
+                            ast,
+                            modified,
+                            fields,
+                            *overwrite,
+                            &config.compute,
+                        );
+                        cli::insert_frontmatter(&frontmatter, ast)
+                    }
+                    FrontmatterCommands::Query {
+                        expression, fields, ..
+                    } => match cli::query(expression, fields) {
+                        Ok((query, fields)) => {
+                            let root = frontmatter.to_value().unwrap_or_default();
+                            let path = input.path().display().to_string();
+                            cli::ResultType::List(
+                                query
+                                    .matches(&root)
+                                    .then(|| {
+                                        cli::Entry::Match(query::Match::new(path, &root, &fields))
+                                    })
+                                    .into_iter()
+                                    .collect(),
+                            )
+                        }
+                        Err(err) => cli::ResultType::Err(err),
+                    },
+                    FrontmatterCommands::Convert { to } => match frontmatter.convert(*to) {
+                        Ok(()) => cli::insert_frontmatter(&frontmatter, ast),
+                        Err(err) => cli::ResultType::Err(cli::Error {
+                            code: cli::ErrorCode::EPRSG,
+                            description: format!(
+                                "Failed to convert the frontmatter to {:?}\n{:#?}",
+                                to, err
+                            ),
+                            fix: None,
+                            url: None,
+                        }),
+                    },
                     FrontmatterCommands::Extract { to_json } => match frontmatter.to_value() {
                         Ok(value) => {
                             let result = if *to_json {
@@ -295,118 +1154,63 @@ fn main() {
                 }),
             }
         }
-        Commands::Convert { format } => match format {
-            convert::Formats::NPF => match convert::npf::from(ast) {
-                Ok(npf) => {
-                    let function = if cli.input.is_tty() {
-                        serde_json::to_string_pretty
-                    } else {
-                        serde_json::to_string
-                    };
-
-                    match function(&npf).map_err(|e| {
-                        cli::ResultType::Err(cli::Error {
-                            description: format!(
-                                "Failed to parse Tumblr NPF struct to JSON string
+        Commands::Convert {
+            format, from_npf, ..
+        } => match (cli::npf_post(file, ast, *from_npf, &config.npf), format) {
+            (Ok(npf), convert::Formats::NPF) => {
+                let function = if input.is_tty() {
+                    serde_json::to_string_pretty
+                } else {
+                    serde_json::to_string
+                };
+
+                match function(&npf).map_err(|e| {
+                    cli::ResultType::Err(cli::Error {
+                        description: format!(
+                            "Failed to parse Tumblr NPF struct to JSON string
                             on line {}, column {}. Used vector: \n{:#?}",
-                                e.line(),
-                                e.column(),
-                                &npf
-                            ),
-                            code: cli::ErrorCode::EPRSG,
-                            url: None,
-                            fix: None,
-                        })
-                    }) {
-                        Ok(s) => cli::ResultType::String(s),
-                        Err(e) => e,
-                    }
+                            e.line(),
+                            e.column(),
+                            &npf
+                        ),
+                        code: cli::ErrorCode::EPRSG,
+                        url: None,
+                        fix: None,
+                    })
+                }) {
+                    Ok(s) => cli::ResultType::String(s),
+                    Err(e) => e,
                 }
-                Err(err) => cli::ResultType::Err(cli::Error {
-                    description: format!(
-                        "Failed to convert to Tumblr NPF format, due to error:\n{:#?}",
-                        err
-                    ),
-                    code: cli::ErrorCode::ECNPF,
-                    url: None,
-                    fix: None,
-                }),
-            },
+            }
+            (Ok(npf), convert::Formats::HTML) => {
+                cli::ResultType::String(convert::npf::html::render_document(&npf))
+            }
+            (Err(err), _) => cli::ResultType::Err(err),
         },
-    };
-
-    if let cli::ListFormat::JSON = &cli.list_format {
-        if cli.input.is_tty() {
-            cli.list_format = cli::ListFormat::PrettyJSON
-        }
-    }
-
-    if let cli::ResultType::Err(e) = result {
-        cli::print_error(e, cli.surpress_errors);
-        return;
-    }
-
-    let str = match cli::result_to_str(result, &cli.list_format) {
-        Ok(s) => s,
-        Err(e) => {
-            cli::print_error(e, cli.surpress_errors);
-            return;
-        }
-    };
-
-    if cli.input.is_std() || !cli.write {
-        if let Err(e) = std::io::stdout().write(str.as_bytes()) {
-            cli::print_error(
-                cli::Error {
-                    code: cli::ErrorCode::EIOWR,
-                    description: format!("Error trying to write result to stdout\n{e:#?}"),
-                    fix: None,
-                    url: None,
-                },
-                cli.surpress_errors,
-            );
+        Commands::Rebase {
+            from,
+            to,
+            asset_root,
+        } => {
+            let from = match from {
+                Some(f) => document_dir(f),
+                None => input_dir(input).to_path_buf(),
+            };
+            let to = to.as_deref().map(document_dir).unwrap_or(from.clone());
+            let mut rebase = links::rebase::Rebase::new(&from, &to);
+            if let [old, new] = asset_root.as_slice() {
+                rebase = rebase.with_assets(old, new);
+            }
+            links::rebase_links(ast, &rebase);
+            links::rebase_images(ast, &rebase);
+            cli::ResultType::Markdown(ast)
         }
-    } else if let Some(f) = cli.input.path().to_str() {
-        let file = std::fs::OpenOptions::new()
-            .write(true)
-            .read(true)
-            .create(true)
-            .open(PathBuf::from(f));
-
-        match file {
-            Ok(mut f) => {
-                if let Err(e) = f.write(str.as_bytes()) {
-                    cli::print_error(
-                        cli::Error {
-                            code: cli::ErrorCode::EIOWR,
-                            description: format!("Error, failed to write to file\n{e:#?}"),
-                            fix: None,
-                            url: None,
-                        },
-                        cli.surpress_errors,
-                    );
-                }
+        Commands::Preview { from_npf, .. } => {
+            match cli::npf_post(file, ast, *from_npf, &config.npf) {
+                Ok(npf) => cli::ResultType::String(convert::npf::ansi::render(&npf)),
+                Err(err) => cli::ResultType::Err(err),
             }
-            Err(e) => cli::print_error(
-                cli::Error {
-                    code: cli::ErrorCode::EIORD,
-                    description: format!("Error, failed to open input's file\n{e:#?}"),
-                    fix: None,
-                    url: None,
-                },
-                cli.surpress_errors,
-            ),
         }
-    } else {
-        cli::print_error(
-            cli::Error {
-                code: cli::ErrorCode::EIOTY,
-                description: format!("Error, output is not a valid file"),
-                fix: None,
-                url: None,
-            },
-            cli.surpress_errors,
-        );
     }
 }
 
@@ -415,8 +1219,14 @@ mod cli {
     use std::{cell::RefCell, fmt};
 
     use comrak::{arena_tree::Node, nodes::Ast};
-    use dot013_mdparser::utils;
+    use dot013_mdparser::config::ConfigErr;
+    use dot013_mdparser::convert::npf::{self, objects};
+    use dot013_mdparser::frontmatter::path::{Path, PathErr};
+    use dot013_mdparser::frontmatter::{query, schema, Frontmatter, FrontmatterErr};
+    use dot013_mdparser::links;
+    use dot013_mdparser::splice;
 
+    #[allow(clippy::upper_case_acronyms)]
     #[derive(Clone, Debug, clap::ValueEnum)]
     pub enum ListFormat {
         Lines,
@@ -426,6 +1236,40 @@ mod cli {
         PrettyJSON,
     }
 
+    /// An item of a listing. Links and images are written as their URL, or as
+    /// objects with their text and position in JSON.
+    #[derive(Debug, serde::Serialize)]
+    #[serde(untagged)]
+    pub enum Entry {
+        Link(links::LinkInfo),
+        Image(links::ImageInfo),
+        Broken(links::check::Broken),
+        Issue(links::audit::Issue),
+        Violation(schema::Violation),
+        Failure(links::normalize::Failure),
+        Match(query::Match),
+    }
+    impl fmt::Display for Entry {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Entry::Link(l) => write!(f, "{}", l),
+                Entry::Image(i) => write!(f, "{}", i),
+                Entry::Broken(b) => write!(f, "{}", b),
+                Entry::Issue(i) => write!(f, "{}", i),
+                Entry::Violation(v) => write!(f, "{}", v),
+                Entry::Failure(e) => write!(f, "{}", e),
+                Entry::Match(m) => write!(f, "{}", m.path),
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, clap::ValueEnum)]
+    pub enum LinkKind {
+        Url,
+        Wikilink,
+    }
+
+    #[allow(clippy::upper_case_acronyms)]
     #[derive(Debug)]
     pub enum ErrorCode {
         EPRSG,
@@ -486,12 +1330,15 @@ mod cli {
         List(Vec<T>),
         String(String),
         Markdown(&'a Node<'a, RefCell<Ast>>),
+        /// Markdown edited directly in its source, instead of re-rendered
+        Source(String),
         Err(Error),
     }
 
     pub fn result_to_str<T: fmt::Display + fmt::Debug + serde::Serialize>(
         result: ResultType<T>,
         list_format: &ListFormat,
+        options: &comrak::Options,
     ) -> Result<String, Error> {
         match result {
             ResultType::List(list) => match list_format {
@@ -534,11 +1381,10 @@ mod cli {
                     fix: None,
                 }),
             },
-            ResultType::String(s) => Ok(s),
+            ResultType::String(s) | ResultType::Source(s) => Ok(s),
             ResultType::Markdown(ast) => {
                 let mut str = vec![];
-                if let Err(e) = comrak::format_commonmark(ast, &utils::default_options(), &mut str)
-                {
+                if let Err(e) = comrak::format_commonmark(ast, options, &mut str) {
                     return Err(Error {
                         code: ErrorCode::EPRSG,
                         description: format!("Error formatting ast back to markdown\n{e:#?}"),
@@ -562,6 +1408,146 @@ mod cli {
         }
     }
 
+    pub fn source_result<'a, T>(result: Result<String, splice::SpliceErr>) -> ResultType<'a, T>
+    where
+        T: fmt::Display + fmt::Debug + serde::Serialize,
+    {
+        match result {
+            Ok(s) => ResultType::Source(s),
+            Err(e) => ResultType::Err(Error {
+                code: ErrorCode::EPRSG,
+                description: format!("Failed to apply the changes to the source\n{e:#?}"),
+                fix: None,
+                url: None,
+            }),
+        }
+    }
+
+    pub fn npf_post<'a>(
+        file: &str,
+        ast: &'a Node<'a, RefCell<Ast>>,
+        from_npf: bool,
+        options: &npf::Options,
+    ) -> Result<objects::Post, Error> {
+        if from_npf {
+            serde_json::from_str::<objects::Post>(file).map_err(|err| Error {
+                description: format!(
+                    "Failed to parse input as a Tumblr NPF post, due to error:\n{:#?}",
+                    err
+                ),
+                code: ErrorCode::EPRSG,
+                url: None,
+                fix: None,
+            })
+        } else {
+            npf::from_with(ast, options).map_err(|err| Error {
+                description: format!(
+                    "Failed to convert to Tumblr NPF format, due to error:\n{:#?}",
+                    err
+                ),
+                code: ErrorCode::ECNPF,
+                url: None,
+                fix: None,
+            })
+        }
+    }
+
+    /// Formats a list found in the file `name`, when processing many files,
+    /// prefixing each item (or the line, for comma separated lists) with it.
+    pub fn prefixed_list<T: fmt::Display>(
+        name: &str,
+        list: &[T],
+        list_format: &ListFormat,
+    ) -> String {
+        let items = list.iter().map(|i| i.to_string());
+        match list_format {
+            ListFormat::Comma => format!("{}:{}\n", name, items.collect::<Vec<_>>().join(",")),
+            _ => items.map(|i| format!("{}:{}\n", name, i)).collect(),
+        }
+    }
+
+    pub fn config_error(err: ConfigErr) -> Error {
+        let (code, path) = match &err {
+            ConfigErr::Io { path, .. } => (ErrorCode::EIORD, path.display()),
+            ConfigErr::Parse { path, .. } => (ErrorCode::EPRSG, path.display()),
+        };
+        Error {
+            code,
+            description: format!("Failed to load the configuration file {path}\n{err:#?}"),
+            fix: None,
+            url: None,
+        }
+    }
+
+    pub fn path_error(path: &str, err: FrontmatterErr) -> Error {
+        let syntax = matches!(err, FrontmatterErr::Path(PathErr::Syntax { .. }));
+        Error {
+            code: ErrorCode::EPRSG,
+            description: format!("Failed to use the frontmatter path {path}\n{err:#?}"),
+            fix: syntax.then(|| {
+                String::from(
+                    "Paths look like author.name, tags[0] or links[-1].url, \
+                    with keys containing dots quoted as [\"og.title\"]",
+                )
+            }),
+            url: None,
+        }
+    }
+
+    /// Writes the changed frontmatter back to the document.
+    pub fn insert_frontmatter<'a, T>(
+        frontmatter: &Frontmatter,
+        ast: &'a Node<'a, RefCell<Ast>>,
+    ) -> ResultType<'a, T>
+    where
+        T: fmt::Display + fmt::Debug + serde::Serialize,
+    {
+        match frontmatter.insert_ast(ast) {
+            Ok(()) => ResultType::Markdown(ast),
+            Err(err) => ResultType::Err(Error {
+                code: ErrorCode::EPRSG,
+                description: format!("Failed to write the frontmatter\n{err:#?}"),
+                fix: None,
+                url: None,
+            }),
+        }
+    }
+
+    /// The query and the paths of the fields to write for each match.
+    pub fn query(expression: &str, fields: &[String]) -> Result<(query::Query, Vec<Path>), Error> {
+        let error = |description| Error {
+            code: ErrorCode::EPRSG,
+            description,
+            fix: None,
+            url: None,
+        };
+        let query = query::Query::parse(expression)
+            .map_err(|e| error(format!("Failed to parse the query\n{e:#?}")))?;
+        let fields = fields
+            .iter()
+            .map(|f| {
+                Path::parse(f).map_err(|e| error(format!("Failed to parse the field {f}\n{e:#?}")))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((query, fields))
+    }
+
+    /// Values of the command line parsed as YAML, so `3` is a number and
+    /// `[a, b]` a list.
+    pub fn yaml_values(values: &[String]) -> Result<Vec<serde_yaml::Value>, Error> {
+        values
+            .iter()
+            .map(|v| {
+                serde_yaml::from_str(v).map_err(|err| Error {
+                    code: ErrorCode::EPRSG,
+                    description: format!("Error parsing value {v:?} to a YAML value:\n{err:#?}"),
+                    fix: None,
+                    url: None,
+                })
+            })
+            .collect()
+    }
+
     pub fn print_error(err: Error, panics: bool) {
         eprintln!("{}", err);
         if panics {
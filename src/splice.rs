@@ -0,0 +1,662 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use comrak::nodes::{AstNode, NodeLink, NodeValue, Sourcepos};
+
+use crate::links;
+use crate::links::references::{self, Definition};
+
+#[derive(Debug)]
+pub enum SpliceErr {
+    /// The node couldn't be found in the source at its position
+    NotFound(Sourcepos),
+    /// Two changes touch the same part of the source
+    Overlap(Range<usize>),
+}
+
+/// The links, wikilinks, images and frontmatter of a document before it is
+/// changed.
+///
+/// After mutating the AST, [`Snapshot::splice`] applies only the changes made
+/// to these nodes to the original source, so everything else stays exactly as
+/// written instead of being re-rendered by `comrak::format_commonmark`. The
+/// document needs to be parsed with `render.sourcepos` set, which
+/// [`crate::utils::parse_document`] does.
+#[derive(Debug)]
+pub struct Snapshot<'a> {
+    root: &'a AstNode<'a>,
+    nodes: Vec<(&'a AstNode<'a>, NodeValue)>,
+    /// Alt text of the images
+    alts: HashMap<*const AstNode<'a>, String>,
+    /// Extended autolinks, which have no position, with the nearest ancestor
+    /// that has one and how many autolinks with the same text come before
+    /// them in it
+    bare: HashMap<*const AstNode<'a>, (&'a AstNode<'a>, usize)>,
+}
+
+pub fn snapshot<'a>(ast: &'a AstNode<'a>) -> Snapshot<'a> {
+    let nodes = ast
+        .descendants()
+        .filter_map(|n| match &n.data.borrow().value {
+            v @ (NodeValue::Link(_)
+            | NodeValue::Image(_)
+            | NodeValue::WikiLink(_)
+            | NodeValue::FrontMatter(_)) => Some((n, v.clone())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let alts = nodes
+        .iter()
+        .filter(|(_, v)| matches!(v, NodeValue::Image(_)))
+        .map(|(n, _)| (*n as *const _, links::text(n)))
+        .collect();
+    let bare = nodes
+        .iter()
+        .filter(|(n, _)| n.data.borrow().sourcepos.start.line == 0)
+        .filter_map(|(n, _)| {
+            let parent = n
+                .ancestors()
+                .skip(1)
+                .find(|a| a.data.borrow().sourcepos.start.line != 0)?;
+            let text = links::text(n);
+            let index = nodes
+                .iter()
+                .take_while(|(other, _)| !std::ptr::eq(*other, *n))
+                .filter(|(other, _)| {
+                    other.data.borrow().sourcepos.start.line == 0
+                        && other.ancestors().any(|a| std::ptr::eq(a, parent))
+                        && links::text(other) == text
+                })
+                .count();
+            Some((*n as *const _, (parent, index)))
+        })
+        .collect();
+    Snapshot {
+        root: ast,
+        nodes,
+        alts,
+        bare,
+    }
+}
+
+impl<'a> Snapshot<'a> {
+    /// Applies the changes made to the tracked nodes since the snapshot to
+    /// `source`, the Markdown the AST was parsed from.
+    pub fn splice(&self, source: &str) -> Result<String, SpliceErr> {
+        let lines = Lines::new(source, self.frontmatter_lines());
+        let mut edits: Vec<(Range<usize>, String)> = vec![];
+        let definitions = references::definitions(source, self.frontmatter_len());
+        // New destination of the definitions already changed, by label
+        let mut defined: HashMap<String, NodeLink> = HashMap::new();
+
+        for (node, old) in &self.nodes {
+            let attached = node.ancestors().any(|a| std::ptr::eq(a, self.root));
+            let ast = node.data.borrow();
+
+            match (old, &ast.value) {
+                (NodeValue::FrontMatter(old), new) => {
+                    if !source.starts_with(old.as_str()) {
+                        return Err(SpliceErr::NotFound(ast.sourcepos));
+                    }
+                    match new {
+                        NodeValue::FrontMatter(new) if attached && new != old => {
+                            edits.push((0..old.len(), new.clone()))
+                        }
+                        _ if !attached => edits.push((0..old.len(), String::new())),
+                        _ => {}
+                    }
+                }
+                (old_value, new) => {
+                    let Some(old) = as_link(old_value) else {
+                        continue;
+                    };
+                    let changed =
+                        as_link(new).filter(|new| new.url != old.url || new.title != old.title);
+                    let alt = match self.alts.get(&(*node as *const _)) {
+                        Some(old) if attached => Some(links::text(node)).filter(|new| new != old),
+                        _ => None,
+                    };
+                    if attached && changed.is_none() && alt.is_none() {
+                        continue;
+                    }
+
+                    let located = match self.bare.get(&(*node as *const _)) {
+                        Some((parent, index)) => lines.find(parent, &links::text(node), *index),
+                        None => lines.locate(ast.sourcepos, |s| Span::parse(s, old_value)),
+                    };
+                    let (start, span) = located.ok_or(SpliceErr::NotFound(ast.sourcepos))?;
+                    let text = &source[start..start + span.len];
+                    if let (
+                        Some(alt),
+                        SpanKind::Inline { open, close } | SpanKind::Reference { open, close },
+                    ) = (alt, &span.kind)
+                    {
+                        edits.push((start + open..start + close, escape(&alt)));
+                    }
+                    let span_edits = match (changed, &span.kind) {
+                        // Reference links keep their label, and their
+                        // definition is changed instead
+                        (Some(new), SpanKind::Reference { open, close }) if attached => {
+                            let label =
+                                references::normalize(references::label(text, *open, *close));
+                            let definition = definitions
+                                .iter()
+                                .find(|d| references::normalize(&d.label) == label);
+                            match (definition, defined.get(&label)) {
+                                (Some(d), None) => {
+                                    edits.push((
+                                        d.range.clone(),
+                                        Definition::format(&d.label, &new.url, &new.title),
+                                    ));
+                                    defined.insert(label, new);
+                                    vec![]
+                                }
+                                (Some(_), Some(l)) if l.url == new.url && l.title == new.title => {
+                                    vec![]
+                                }
+                                _ => span.change(text, &old, &new),
+                            }
+                        }
+                        (Some(new), _) if attached => span.change(text, &old, &new),
+                        (None, _) if attached => vec![],
+                        // Removed with its text, which is still in the node
+                        _ if !attached && node.first_child().is_some() => {
+                            vec![(0..span.len, String::new())]
+                        }
+                        _ => span.unwrap(),
+                    };
+                    edits.extend(
+                        span_edits
+                            .into_iter()
+                            .map(|(r, s)| (r.start + start..r.end + start, s)),
+                    );
+                }
+            }
+        }
+
+        // Frontmatter added after the snapshot
+        if let Some(NodeValue::FrontMatter(f)) = self
+            .root
+            .first_child()
+            .map(|c| c.data.borrow().value.clone())
+        {
+            let tracked = self
+                .nodes
+                .iter()
+                .any(|(_, v)| matches!(v, NodeValue::FrontMatter(_)));
+            if !tracked {
+                edits.push((0..0, f));
+            }
+        }
+
+        apply(source, edits)
+    }
+
+    /// Length of the frontmatter from before it was changed.
+    fn frontmatter_len(&self) -> usize {
+        self.nodes
+            .iter()
+            .find_map(|(_, v)| match v {
+                NodeValue::FrontMatter(f) => Some(f.len()),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    /// The frontmatter lines, like [`frontmatter_lines`], from before the
+    /// frontmatter was changed.
+    fn frontmatter_lines(&self) -> usize {
+        self.nodes
+            .iter()
+            .find_map(|(_, v)| match v {
+                NodeValue::FrontMatter(f) => Some(f.matches('\n').count()),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+}
+
+/// Replaces each range of `source`, failing if any of them overlap.
+pub(crate) fn apply(
+    source: &str,
+    mut edits: Vec<(Range<usize>, String)>,
+) -> Result<String, SpliceErr> {
+    edits.sort_by_key(|(r, _)| (r.start, r.end));
+    let mut out = String::with_capacity(source.len());
+    let mut last = 0;
+    for (range, replacement) in edits {
+        if range.start < last {
+            return Err(SpliceErr::Overlap(range));
+        }
+        out.push_str(&source[last..range.start]);
+        out.push_str(&replacement);
+        last = range.end;
+    }
+    out.push_str(&source[last..]);
+
+    Ok(out)
+}
+
+pub(crate) struct Lines<'s> {
+    source: &'s str,
+    starts: Vec<usize>,
+    offset: usize,
+}
+impl<'s> Lines<'s> {
+    pub(crate) fn new(source: &'s str, offset: usize) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            source,
+            starts,
+            offset,
+        }
+    }
+    pub(crate) fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        let start = self.starts.get((line + self.offset).checked_sub(1)?)?;
+        Some(start + column.checked_sub(1)?)
+    }
+    /// Finds the node's text in the source. The end of inline nodes is always
+    /// on the right line, but their start line isn't for nodes spanning many
+    /// lines, and autolinks are one column to the right, so every line up to
+    /// the end and both columns are tried until `parse` accepts the text.
+    pub(crate) fn locate<T, F>(&self, pos: Sourcepos, parse: F) -> Option<(usize, T)>
+    where
+        F: Fn(&str) -> Option<T>,
+    {
+        if pos.start.line == 0 || pos.end.line == 0 {
+            return None;
+        }
+        [0, 1].into_iter().find_map(|shift| {
+            let end = self.offset(pos.end.line, pos.end.column.checked_sub(shift)?)? + 1;
+            let column = pos.start.column.checked_sub(shift)?;
+            std::iter::once(pos.start.line)
+                .chain((1..=pos.end.line).rev())
+                .filter_map(|line| self.offset(line, column))
+                .filter(|start| *start < end)
+                .find_map(|start| Some((start, parse(self.source.get(start..end)?)?)))
+        })
+    }
+    /// Finds the `index`th occurrence of `text` in `parent`, for the extended
+    /// autolinks comrak doesn't give a position.
+    fn find<'a>(&self, parent: &'a AstNode<'a>, text: &str, index: usize) -> Option<(usize, Span)> {
+        let pos = parent.data.borrow().sourcepos;
+        let start = self.offset(pos.start.line, pos.start.column)?;
+        let end = self.offset(pos.end.line, pos.end.column)? + 1;
+        let (offset, _) = self
+            .source
+            .get(start..end)?
+            .match_indices(text)
+            .nth(index)?;
+        let span = Span {
+            len: text.len(),
+            kind: SpanKind::Bare,
+        };
+        Some((start + offset, span))
+    }
+    /// Line and column, both starting at 1, of the byte at `offset`.
+    pub(crate) fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|s| *s <= offset);
+        (line, offset - self.starts[line - 1] + 1)
+    }
+}
+
+/// comrak parses the document without its frontmatter, so the lines of every
+/// other node are offset by the frontmatter's lines.
+pub(crate) fn frontmatter_lines<'a>(ast: &'a AstNode<'a>) -> usize {
+    match ast.first_child().map(|c| c.data.borrow().value.clone()) {
+        Some(NodeValue::FrontMatter(f)) => f.matches('\n').count(),
+        _ => 0,
+    }
+}
+
+/// The parts of a link or image in the source, relative to its start. `open`
+/// is the length of the opening bracket, and `close` the position of the
+/// closing one.
+#[derive(Debug)]
+pub(crate) struct Span {
+    pub(crate) len: usize,
+    pub(crate) kind: SpanKind,
+}
+#[derive(Debug)]
+pub(crate) enum SpanKind {
+    /// `[text](destination "title")`
+    Inline { open: usize, close: usize },
+    /// `[text][label]`, `[label][]` and `[label]`
+    Reference { open: usize, close: usize },
+    /// `<https://example.com>`
+    Autolink,
+    /// `www.example.com` and `https://example.com`, with the extension
+    Bare,
+    /// `[[Page]]` and `[[Page|alias]]`, with the range of the text shown
+    Wikilink { label: Range<usize> },
+}
+
+impl Span {
+    pub(crate) fn parse(text: &str, value: &NodeValue) -> Option<Self> {
+        let len = text.len();
+        let image = matches!(value, NodeValue::Image(_));
+        if let NodeValue::WikiLink(_) = value {
+            let inner = text.strip_prefix("[[")?.strip_suffix("]]")?;
+            let label = match inner.find('|') {
+                Some(pipe) => pipe + 3..len - 2,
+                None => 2..len - 2,
+            };
+            return Some(Self {
+                len,
+                kind: SpanKind::Wikilink { label },
+            });
+        }
+        if !image && text.starts_with('<') && text.ends_with('>') {
+            return Some(Self {
+                len,
+                kind: SpanKind::Autolink,
+            });
+        }
+
+        let open = if image { "![" } else { "[" };
+        if !text.starts_with(open) {
+            return None;
+        }
+        let open = open.len();
+
+        let mut depth = 0;
+        let mut escaped = false;
+        let close = text.char_indices().skip(open).find_map(|(i, c)| {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '[' => depth += 1,
+                ']' if depth == 0 => return Some(i),
+                ']' => depth -= 1,
+                _ => {}
+            }
+            None
+        })?;
+
+        let rest = &text[close + 1..];
+        let kind = if rest.starts_with('(') && rest.ends_with(')') {
+            SpanKind::Inline { open, close }
+        } else if rest.is_empty() || (rest.starts_with('[') && rest.ends_with(']')) {
+            SpanKind::Reference { open, close }
+        } else {
+            return None;
+        };
+        Some(Self { len, kind })
+    }
+
+    /// Edits that turn the link into its text, keeping any formatting.
+    fn unwrap(&self) -> Vec<(Range<usize>, String)> {
+        match self.kind {
+            SpanKind::Inline { open, close } | SpanKind::Reference { open, close } => {
+                vec![(0..open, String::new()), (close..self.len, String::new())]
+            }
+            SpanKind::Autolink => vec![
+                (0..1, String::new()),
+                (self.len - 1..self.len, String::new()),
+            ],
+            SpanKind::Bare => vec![],
+            SpanKind::Wikilink { ref label } => vec![
+                (0..label.start, String::new()),
+                (label.end..self.len, String::new()),
+            ],
+        }
+    }
+
+    /// Edits that change the link's destination and title, touching only the
+    /// parts that changed.
+    fn change(&self, text: &str, old: &NodeLink, new: &NodeLink) -> Vec<(Range<usize>, String)> {
+        match self.kind {
+            SpanKind::Inline { close, .. } => {
+                let inner = close + 2..self.len - 1;
+                let (destination, title) = split_destination(&text[inner.clone()]);
+                let destination = destination.start + inner.start..destination.end + inner.start;
+                let title = title.start + inner.start..title.end + inner.start;
+
+                let mut edits = vec![];
+                if new.url != old.url {
+                    edits.push((destination, format_destination(&new.url)));
+                }
+                if new.title != old.title {
+                    edits.push((title, format_title(&new.title)));
+                }
+                edits
+            }
+            SpanKind::Reference { close, .. } => vec![(
+                close + 1..self.len,
+                format!(
+                    "({}{})",
+                    format_destination(&new.url),
+                    format_title(&new.title)
+                ),
+            )],
+            SpanKind::Autolink if is_autolink(new) => vec![(1..self.len - 1, new.url.clone())],
+            SpanKind::Bare if is_autolink(new) => vec![(0..self.len, format!("<{}>", new.url))],
+            SpanKind::Autolink | SpanKind::Bare => {
+                let text = match self.kind {
+                    SpanKind::Autolink => &text[1..self.len - 1],
+                    _ => text,
+                };
+                vec![(
+                    0..self.len,
+                    format!(
+                        "[{}]({}{})",
+                        text,
+                        format_destination(&new.url),
+                        format_title(&new.title)
+                    ),
+                )]
+            }
+            SpanKind::Wikilink { ref label } => {
+                let label = &text[label.clone()];
+                let link = if new.url == label {
+                    format!("[[{}]]", new.url)
+                } else {
+                    format!("[[{}|{}]]", new.url, label)
+                };
+                vec![(0..self.len, link)]
+            }
+        }
+    }
+}
+
+/// Destination and title of links and images, and of wikilinks, which don't
+/// have a title.
+fn as_link(value: &NodeValue) -> Option<NodeLink> {
+    match value {
+        NodeValue::Link(l) | NodeValue::Image(l) => Some(l.clone()),
+        NodeValue::WikiLink(l) => Some(NodeLink {
+            url: l.url.clone(),
+            title: String::new(),
+        }),
+        _ => None,
+    }
+}
+
+/// Whether the link can be written as `<destination>`.
+fn is_autolink(link: &NodeLink) -> bool {
+    link.title.is_empty()
+        && link.url.contains(':')
+        && !link
+            .url
+            .contains(|c: char| c.is_whitespace() || c == '<' || c == '>')
+}
+
+/// Splits what is inside an inline link's parenthesis into the destination
+/// and the rest, whitespace and title, after it.
+fn split_destination(inner: &str) -> (Range<usize>, Range<usize>) {
+    let start = inner.len() - inner.trim_start().len();
+    let rest = &inner[start..];
+
+    let len = if rest.starts_with('<') {
+        let mut escaped = false;
+        rest.char_indices()
+            .skip(1)
+            .find_map(|(i, c)| match c {
+                _ if escaped => {
+                    escaped = false;
+                    None
+                }
+                '\\' => {
+                    escaped = true;
+                    None
+                }
+                '>' => Some(i + 1),
+                _ => None,
+            })
+            .unwrap_or(rest.len())
+    } else {
+        rest.find(char::is_whitespace).unwrap_or(rest.len())
+    };
+
+    (start..start + len, start + len..inner.len())
+}
+
+pub(crate) fn format_destination(url: &str) -> String {
+    let depth = url.chars().try_fold(0i32, |depth, c| match c {
+        '(' => Some(depth + 1),
+        ')' if depth == 0 => None,
+        ')' => Some(depth - 1),
+        _ => Some(depth),
+    });
+    if url.is_empty()
+        || depth != Some(0)
+        || url.contains(|c: char| c.is_whitespace() || c == '<' || c == '>')
+    {
+        format!("<{}>", url.replace('<', "\\<").replace('>', "\\>"))
+    } else {
+        String::from(url)
+    }
+}
+
+/// Escapes the characters that would format text or end a link's text.
+fn escape(text: &str) -> String {
+    text.chars().fold(String::new(), |mut out, c| {
+        if "\\[]*_`<>".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+        out
+    })
+}
+
+pub(crate) fn format_title(title: &str) -> String {
+    if title.is_empty() {
+        String::new()
+    } else {
+        format!(" \"{}\"", title.replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use comrak::Arena;
+
+    use crate::frontmatter::{self, Frontmatter};
+    use crate::{links, utils};
+
+    #[test]
+    fn splice_changes() {
+        let markdown = "---\n\
+            title: Test\n\
+            ---\n\
+            \n\
+            Some __strong__ text, with a line that is long enough to be wrapped by comrak when it is rendered, \
+            [a link](https://guz.one \"Title\") and\n\
+            a [multi\n\
+            line](https://example.com) link.\n\
+            \n\
+            * Item ![An *image*](cat.png)\n\
+            * [Reference][ref], [Guz][guz] and <https://guz.one>\n\
+            * [[Page]], [[Other Page|alias]] and [[Removed]]\n\
+            \n\
+            [ref]: https://example.com\n\
+            [guz]: https://guz.one\n";
+
+        let mut options = utils::default_options();
+        options.extension.wikilinks_title_after_pipe = true;
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, markdown, &options);
+        let snapshot = super::snapshot(ast);
+
+        links::replace_links(ast, "https://guz.one", "https://guz.tumblr.com");
+        links::remove_link(ast, "https://example.com");
+        links::set_image_alt(&arena, ast, "cat.png", "A *dog*");
+        links::replace_images(ast, "cat.png", "dog (1).png");
+        links::replace_wikilinks(ast, "Page", "New Page");
+        links::replace_wikilinks(ast, "Other Page", "alias");
+        links::remove_wikilink(ast, "Removed");
+        let mut f = Frontmatter::try_from(ast).unwrap();
+        f.insert(
+            String::from("title"),
+            serde_yaml::to_value("Changed").unwrap(),
+        );
+        f.insert_ast(ast).unwrap();
+
+        assert_eq!(
+            snapshot.splice(markdown).unwrap(),
+            "---\n\
+            title: Changed\n\
+            ---\n\
+            \n\
+            Some __strong__ text, with a line that is long enough to be wrapped by comrak when it is rendered, \
+            [a link](https://guz.tumblr.com \"Title\") and\n\
+            a multi\n\
+            line link.\n\
+            \n\
+            * Item ![A \\*dog\\*](<dog (1).png>)\n\
+            * Reference, [Guz][guz] and <https://guz.tumblr.com>\n\
+            * [[New Page|Page]], [[alias]] and Removed\n\
+            \n\
+            [ref]: https://example.com\n\
+            [guz]: https://guz.tumblr.com\n"
+        );
+    }
+
+    #[test]
+    fn splice_new_frontmatter() {
+        let markdown = "Text  \nwith *hard* break [link](https://guz.one)\n";
+
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, markdown, &utils::default_options());
+        let snapshot = super::snapshot(ast);
+
+        frontmatter::prepend_empty(&arena, ast);
+        let mut f = Frontmatter::new();
+        f.insert(String::from("tags"), serde_yaml::to_value(["art"]).unwrap());
+        f.insert_ast(ast).unwrap();
+        links::replace_links(ast, "https://guz.one", "https://example.com");
+
+        assert_eq!(
+            snapshot.splice(markdown).unwrap(),
+            "---\n\
+            tags:\n\
+            - art\n\
+            ---\n\
+            \n\
+            Text  \nwith *hard* break [link](https://example.com)\n"
+        );
+    }
+
+    #[test]
+    fn splice_extended_autolinks() {
+        let markdown = "See www.guz.one, https://guz.one and\n\
+            https://guz.one again, or <https://guz.one>.\n";
+
+        let mut options = utils::default_options();
+        options.extension.autolink = true;
+        let arena = Arena::new();
+        let ast = utils::parse_document(&arena, markdown, &options);
+        let snapshot = super::snapshot(ast);
+
+        links::replace_links(ast, "http://www.guz.one", "https://example.com");
+        links::replace_links(ast, "https://guz.one", "https://guz.tumblr.com");
+
+        assert_eq!(
+            snapshot.splice(markdown).unwrap(),
+            "See <https://example.com>, <https://guz.tumblr.com> and\n\
+            <https://guz.tumblr.com> again, or <https://guz.tumblr.com>.\n"
+        );
+    }
+}
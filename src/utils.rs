@@ -10,6 +10,80 @@ pub fn default_options() -> comrak::Options {
     opts
 }
 
+/// Parses the document like `comrak::parse_document`, with the frontmatter
+/// delimiter of its format: `---`, `+++` or `;;;`. Bare JSON objects are split
+/// off and added as the frontmatter node, see [`crate::frontmatter::format`].
+///
+/// Inline nodes get their source positions too, which [`crate::splice`] and
+/// the link listings need to find them. Only the parse is affected, the
+/// options the document is rendered with are left as given.
+pub fn parse_document<'a>(
+    arena: &'a comrak::Arena<comrak::nodes::AstNode<'a>>,
+    source: &str,
+    options: &comrak::Options,
+) -> &'a comrak::nodes::AstNode<'a> {
+    use crate::frontmatter::format::{self, Format};
+
+    let mut options = options.clone();
+    options.render.sourcepos = true;
+    if let Some(len) = format::bare_json(source) {
+        options.extension.front_matter_delimiter = None;
+        let ast = comrak::parse_document(arena, &source[len..], &options);
+        let node = arena.alloc(comrak::arena_tree::Node::new(std::cell::RefCell::from(
+            comrak::nodes::Ast::new(
+                comrak::nodes::NodeValue::FrontMatter(String::from(&source[..len])),
+                comrak::nodes::LineColumn { line: 1, column: 1 },
+            ),
+        )));
+        ast.prepend(node);
+        return ast;
+    }
+    if let Some(format) = Format::delimited(source) {
+        options.extension.front_matter_delimiter = Some(format.delimiter().to_owned());
+    }
+    comrak::parse_document(arena, source, &options)
+}
+
+/// Replaces the wikilink nodes by their literal text, `[[Page]]` or
+/// `[[Page|alias]]`, so `comrak::format_commonmark` writes them as they are
+/// usually written instead of percent-encoded and always with an alias. Run
+/// it right before formatting, as the nodes stop being wikilinks.
+pub fn literal_wikilinks<'a>(ast: &'a comrak::nodes::AstNode<'a>) {
+    for node in ast.descendants().collect::<Vec<_>>() {
+        let url = match &node.data.borrow().value {
+            comrak::nodes::NodeValue::WikiLink(l) => l.url.clone(),
+            _ => continue,
+        };
+        let text = node
+            .descendants()
+            .filter_map(|c| match &c.data.borrow().value {
+                comrak::nodes::NodeValue::Text(t) => Some(t.clone()),
+                _ => None,
+            })
+            .collect::<String>();
+        node.children().for_each(|c| c.detach());
+        node.data.borrow_mut().value = comrak::nodes::NodeValue::HtmlInline(if text == url {
+            format!("[[{}]]", url)
+        } else {
+            format!("[[{}|{}]]", url, text)
+        });
+    }
+}
+
+/// A number of days since 1970-01-01 as a date, like `2024-05-01`.
+pub fn format_date(days: i64) -> String {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
 pub fn iter_nodes<'a, F>(node: &'a comrak::nodes::AstNode<'a>, f: &F)
 where
     F: Fn(&'a comrak::nodes::AstNode<'a>),